@@ -0,0 +1,193 @@
+use std::{
+    path::PathBuf,
+    time::Duration,
+};
+
+use log::LevelFilter;
+
+/**
+The default size of the heap given to each index writer.
+*/
+pub const DEFAULT_HEAP_SIZE: usize = 50_000_000;
+
+/**
+The smallest heap that can be given to an index writer.
+
+Tantivy needs at least this much for each thread that indexes documents.
+*/
+pub const MIN_HEAP_SIZE: usize = 3_000_000;
+
+/**
+Configuration for the whole logging pipeline.
+
+A config is given to a `Store` when it's created, and everything that
+works with that store (indexers, loggers, and searchers) picks up the
+settings that are relevant to it.
+*/
+#[derive(Debug, Clone)]
+pub struct Config {
+    /**
+    The size of the heap given to each index writer, in bytes.
+    */
+    pub heap_size: usize,
+    /**
+    When indexers commit the documents written to them.
+    */
+    pub commit: CommitPolicy,
+    /**
+    The most verbose level of records to log.
+    */
+    pub level: LevelFilter,
+    /**
+    The fields to search when a query term doesn't name one.
+
+    If there aren't any then every text field in an index is searched.
+    */
+    pub default_fields: Vec<String>,
+    /**
+    The separator between components of flattened field names.
+    */
+    pub separator: String,
+    /**
+    Where indexes are kept.
+    */
+    pub storage: Storage,
+    /**
+    When searchers pick up documents committed to an index.
+    */
+    pub reload: ReloadPolicy,
+}
+
+/**
+When documents written to an index are committed.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommitPolicy {
+    /**
+    Commit after every document.
+    */
+    EachDoc,
+    /**
+    Commit after the given number of documents have been written to an index,
+    or when the interval has passed since the first uncommitted document,
+    whichever comes first.
+
+    The interval is checked when documents are indexed, and by calling
+    `Indexer::commit_elapsed`. Loggers call it on a timer, so the last records
+    in a batch are committed once the interval passes even if no more arrive.
+    Documents that haven't been committed yet aren't visible to searches.
+    */
+    Batch {
+        docs: usize,
+        interval: Duration,
+    },
+}
+
+/**
+When searchers pick up documents that have been committed to an index.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReloadPolicy {
+    /**
+    Reload an index's searchers on the first search after it's committed.
+
+    Committed documents are visible to the very next search, and indexes
+    that haven't changed aren't reloaded.
+    */
+    OnCommit,
+    /**
+    Only reload an index's searchers when `reload` is called on the store or a searcher.
+
+    Indexes are still loaded on their first search, but later commits aren't
+    visible until they're reloaded.
+    */
+    Manual,
+}
+
+/**
+Where indexes are kept.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Storage {
+    /**
+    Keep indexes in memory.
+    */
+    Ram,
+    /**
+    Keep indexes on disk, with a subdirectory for each index.
+    */
+    Dir(PathBuf),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            heap_size: DEFAULT_HEAP_SIZE,
+            commit: CommitPolicy::EachDoc,
+            level: LevelFilter::Info,
+            default_fields: Vec::new(),
+            separator: ".".to_owned(),
+            storage: Storage::Ram,
+            reload: ReloadPolicy::OnCommit,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    /**
+    Set the size of the heap given to each index writer.
+
+    Writers use a thread per CPU and split the heap between them. If that
+    would leave a thread with less than `MIN_HEAP_SIZE` then fewer threads
+    are used. A heap smaller than `MIN_HEAP_SIZE` is invalid.
+    */
+    pub fn heap_size(mut self, heap_size: usize) -> Self {
+        self.heap_size = heap_size;
+        self
+    }
+
+    /**
+    Check that the config can be used.
+    */
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        if self.heap_size < MIN_HEAP_SIZE {
+            return Err(crate::Error::InvalidConfig(format!("the heap size {} is smaller than the minimum of {}", self.heap_size, MIN_HEAP_SIZE)));
+        }
+
+        Ok(())
+    }
+
+    pub fn commit(mut self, commit: CommitPolicy) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn default_field(mut self, field: impl Into<String>) -> Self {
+        self.default_fields.push(field.into());
+        self
+    }
+
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn reload(mut self, reload: ReloadPolicy) -> Self {
+        self.reload = reload;
+        self
+    }
+}
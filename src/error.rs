@@ -0,0 +1,120 @@
+use std::{
+    error,
+    fmt,
+    io,
+};
+
+use tantivy::TantivyError;
+
+use crate::{
+    index::IndexId,
+    schema::Invalid,
+};
+
+/**
+An error logging or searching records.
+*/
+#[derive(Debug)]
+pub enum Error {
+    /**
+    A value couldn't be serialized into a document.
+    */
+    Serialize(Invalid),
+    /**
+    A document can't be indexed.
+    */
+    InvalidDoc(String),
+    /**
+    A query couldn't be parsed.
+    */
+    QueryParse(String),
+    /**
+    An index that was expected to exist is missing.
+    */
+    MissingIndex(IndexId),
+    /**
+    The segments of an index couldn't be merged.
+    */
+    Merge(IndexId),
+    /**
+    The store's config is invalid.
+    */
+    InvalidConfig(String),
+    /**
+    The store was opened read-only, so it can't be written to.
+    */
+    ReadOnly,
+    /**
+    Only some of a batch of documents were indexed before one failed.
+    */
+    Partial {
+        indexed: usize,
+        err: Box<Error>,
+    },
+    /**
+    A search was cancelled or ran out of time before it finished.
+    */
+    Cancelled,
+    Tantivy(TantivyError),
+    Json(serde_json::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Serialize(ref err) => write!(f, "failed to serialize a document: {}", err),
+            Error::InvalidDoc(ref msg) => write!(f, "invalid document: {}", msg),
+            Error::QueryParse(ref msg) => write!(f, "failed to parse a query: {}", msg),
+            Error::MissingIndex(index) => write!(f, "missing index {}", index),
+            Error::Merge(index) => write!(f, "failed to merge index {}", index),
+            Error::InvalidConfig(ref msg) => write!(f, "invalid config: {}", msg),
+            Error::ReadOnly => write!(f, "the store is read-only"),
+            Error::Partial { indexed, ref err } => write!(f, "failed after indexing {} documents: {}", indexed, err),
+            Error::Cancelled => write!(f, "the search was cancelled"),
+            Error::Tantivy(ref err) => write!(f, "{:?}", err),
+            Error::Json(ref err) => write!(f, "{}", err),
+            Error::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Serialize(ref err) => Some(err),
+            Error::Partial { ref err, .. } => Some(&**err),
+            Error::Json(ref err) => Some(err),
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        "tantivy-log error"
+    }
+}
+
+impl From<Invalid> for Error {
+    fn from(err: Invalid) -> Self {
+        Error::Serialize(err)
+    }
+}
+
+impl From<TantivyError> for Error {
+    fn from(err: TantivyError) -> Self {
+        Error::Tantivy(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -1,55 +1,1280 @@
 use std::{
-    collections::HashMap,
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    io::{
+        BufRead,
+        BufReader,
+        Read,
+    },
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        RwLock,
+    },
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+    },
 };
 
+use serde::Serialize;
+
 use tantivy::{
-    IndexWriter,
+    collector::CountCollector,
+    query::TermQuery,
+    schema::IndexRecordOption,
 };
 
-use serde::Serialize;
-
 use crate::{
-    schema::Doc,
-    store::Store,
+    config::CommitPolicy,
+    schema::{
+        Doc,
+        IndexableDoc,
+        Options,
+    },
+    searcher::parse_query,
+    store::{
+        doc_time,
+        Store,
+        TimeRange,
+    },
+    sync::{
+        lock,
+        read,
+        write,
+    },
 };
 
 pub type IndexId = u64;
 
+/**
+A sink for documents that couldn't be indexed.
+
+The sink is given the original document as JSON along with the error that
+stopped it from being indexed, so it can be inspected or retried later.
+Documents that can't be serialized as JSON are given as `null`.
+*/
+pub type DeadLetters = Arc<dyn Fn(serde_json::Value, &crate::Error) + Send + Sync>;
+
 /**
 An indexer for a store.
+
+Documents are committed according to the store's commit policy, unless
+the indexer is given its own. Any documents that haven't been committed
+when the indexer is dropped are committed then.
 */
 pub struct Indexer {
     store: Store,
-    writers: HashMap<IndexId, IndexWriter>,
+    pending: HashMap<IndexId, Pending>,
+    commit: CommitPolicy,
+    options: Options,
+    widen: bool,
+    dead_letters: Option<DeadLetters>,
+}
+
+impl Drop for Indexer {
+    fn drop(&mut self) {
+        // Panicking in drop while already unwinding would abort the process
+        // so failures to commit are ignored
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| self.flush()));
+    }
+}
+
+/**
+The result of ingesting newline-delimited JSON.
+*/
+#[derive(Debug)]
+pub struct Ingested {
+    /**
+    The number of lines that were indexed.
+    */
+    pub indexed: usize,
+    /**
+    The lines that couldn't be indexed, by their line number starting from `1`.
+    */
+    pub failed: Vec<(usize, crate::Error)>,
+}
+
+/**
+Documents that have been written to an index but not committed.
+*/
+struct Pending {
+    docs: usize,
+    since: Instant,
+    times: Option<TimeRange>,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Pending {
+            docs: 0,
+            since: Instant::now(),
+            times: None,
+        }
+    }
+
+    fn add(&mut self, time: Option<SystemTime>) {
+        self.docs += 1;
+
+        if let Some(time) = time {
+            match self.times {
+                Some(ref mut times) => times.extend(time),
+                None => self.times = Some(TimeRange::at(time)),
+            }
+        }
+    }
+
+    /**
+    Whether the documents should be committed according to the given policy.
+    */
+    fn is_due(&self, commit: CommitPolicy) -> bool {
+        match commit {
+            CommitPolicy::EachDoc => true,
+            CommitPolicy::Batch { docs, interval } => self.docs >= docs || self.since.elapsed() >= interval,
+        }
+    }
+
+    /**
+    Whether the documents have been waiting longer than the policy's interval.
+    */
+    fn is_elapsed(&self, commit: CommitPolicy) -> bool {
+        match commit {
+            CommitPolicy::EachDoc => false,
+            CommitPolicy::Batch { interval, .. } => self.since.elapsed() >= interval,
+        }
+    }
+}
+
+/**
+How often batches need to be checked to commit them once their interval has passed.
+*/
+fn commit_interval(commit: CommitPolicy) -> Option<Duration> {
+    match commit {
+        CommitPolicy::Batch { interval, .. } if interval > Duration::from_secs(0) => Some(interval),
+        _ => None,
+    }
+}
+
+/**
+Record the time range of documents that were pending in an index after they're committed.
+*/
+fn record_committed(store: &Store, index: IndexId, pending: Pending) {
+    if let Some(times) = pending.times {
+        store.record_times(index, times);
+    }
+
+    store.record_commit(index, SystemTime::now());
 }
 
 impl Indexer {
     pub fn new(store: Store) -> Self {
         Indexer {
+            options: Options::default().separator(store.config().separator.clone()),
+            commit: store.config().commit,
             store,
-            writers: HashMap::new()
+            pending: HashMap::new(),
+            widen: false,
+            dead_letters: None,
+        }
+    }
+
+    /**
+    Use the given options when building documents.
+    */
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /**
+    Commit documents using the given policy instead of the store's.
+    */
+    pub fn with_commit_policy(mut self, commit: CommitPolicy) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /**
+    Widen existing indexes instead of creating new ones for documents with extra fields.
+
+    Normally, a document with an extra field gets a whole new index. When widening,
+    a document whose fields are a superset of an existing index's will replace that
+    index with a wider one, copying its documents across. Documents with the narrower
+    shape are then written to the wider index too.
+
+    This keeps the number of indexes down when records have optional fields, but
+    copying documents is expensive for large indexes.
+    */
+    pub fn widening(mut self) -> Self {
+        self.widen = true;
+        self
+    }
+
+    /**
+    Send documents that fail to index to the given sink.
+
+    The error is still returned to the caller, so the sink is for keeping
+    the documents themselves around. Documents are sent to the sink if they
+    can't be built, written, or committed.
+    */
+    pub fn with_dead_letters(mut self, sink: impl Fn(serde_json::Value, &crate::Error) + Send + Sync + 'static) -> Self {
+        self.dead_letters = Some(Arc::new(sink));
+        self
+    }
+
+    /**
+    A new indexer with the same configuration as this one, but nothing pending.
+    */
+    pub(crate) fn configured(&self) -> Indexer {
+        Indexer {
+            store: self.store.clone(),
+            pending: HashMap::new(),
+            commit: self.commit,
+            options: self.options.clone(),
+            widen: self.widen,
+            dead_letters: self.dead_letters.clone(),
         }
     }
 
+    pub(crate) fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub(crate) fn dead_letters(&self) -> Option<DeadLetters> {
+        self.dead_letters.clone()
+    }
+
     pub fn index(&mut self, doc: impl Serialize) -> Result<(), crate::Error> {
-        let doc = Doc::build(doc)?;
+        let indexed = self.build(&doc).and_then(|built| self.index_built(&built));
 
-        if let Some(ref mut writer) = self.writers.get_mut(&doc.index()) {
-            let i = doc.indexable();
+        if let Err(ref err) = indexed {
+            self.dead_letter(&doc, err);
+        }
 
-            writer.add_document(i.doc);
-            writer.commit()?;
-        } else {
-            let i = doc.indexable();
+        indexed
+    }
+
+    /**
+    Index a document that's already been built.
+
+    The document should've been built with the same options as the indexer.
+    If it fails to index then its flattened fields are sent to the dead letter sink.
+    */
+    pub fn index_doc(&mut self, doc: Doc) -> Result<(), crate::Error> {
+        let indexed = self.index_built(&doc);
+
+        // The document is only converted back into JSON if there's somewhere to send it
+        if let (Err(err), Some(_)) = (&indexed, &self.dead_letters) {
+            self.dead_letter(doc.to_json_value(), err);
+        }
+
+        indexed
+    }
+
+    fn index_built(&mut self, doc: &Doc) -> Result<(), crate::Error> {
+        let index = self.add(doc)?;
+
+        if self.pending[&index].is_due(self.commit) {
+            self.commit(index)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    Index a batch of documents, committing each index they're written to once at the end.
+
+    This ignores the commit policy, so it's much cheaper than indexing each document
+    on its own when backfilling. The number of documents indexed is returned.
+
+    If a document can't be indexed then the documents before it are still committed,
+    and a `Partial` error is returned with the number of documents that were indexed.
+    */
+    pub fn index_all<I>(&mut self, docs: I) -> Result<usize, crate::Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let mut indexed = 0;
+
+        for doc in docs {
+            let added = self.build(&doc).and_then(|built| self.add(&built));
+
+            if let Err(err) = added {
+                self.dead_letter(&doc, &err);
+                self.flush()?;
+
+                return Err(crate::Error::Partial {
+                    indexed,
+                    err: Box::new(err),
+                });
+            }
+
+            indexed += 1;
+        }
+
+        self.flush()?;
+
+        Ok(indexed)
+    }
+
+    /**
+    Commit the documents in any index that have been waiting longer than the batch interval.
+
+    The interval is otherwise only checked when documents are written to an index,
+    so this can be called periodically to make sure the last documents in a batch
+    become visible even if no more are indexed. This does nothing unless the
+    indexer's commit policy is `CommitPolicy::Batch`.
+    */
+    pub fn commit_elapsed(&mut self) -> Result<(), crate::Error> {
+        let commit = self.commit;
+
+        let elapsed = self.pending
+            .iter()
+            .filter(|(_, pending)| pending.is_elapsed(commit))
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+
+        for index in elapsed {
+            self.commit(index)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    How often `commit_elapsed` needs to be called to commit batches on time.
+
+    Indexers that commit each document don't need it to be called at all.
+    */
+    pub(crate) fn commit_interval(&self) -> Option<Duration> {
+        commit_interval(self.commit)
+    }
+
+    /**
+    Commit any documents that have been written but not committed yet.
+    */
+    pub fn flush(&mut self) -> Result<(), crate::Error> {
+        let pending = self.pending.keys().cloned().collect::<Vec<_>>();
+
+        for index in pending {
+            self.commit(index)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    Index newline-delimited JSON, with a document on each line.
+
+    Blank lines are skipped. Lines that aren't valid JSON or can't be indexed
+    are reported in the result without stopping the rest of the stream. Like
+    `index_all`, each index is committed once at the end. Lines that aren't
+    valid JSON are sent to the dead letter sink as strings.
+
+    Failing to read from the reader stops the stream, but documents that were
+    already read are still committed.
+    */
+    pub fn index_ndjson(&mut self, reader: impl Read) -> Result<Ingested, crate::Error> {
+        let mut ingested = Ingested {
+            indexed: 0,
+            failed: Vec::new(),
+        };
+
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    self.flush()?;
+
+                    return Err(err.into());
+                },
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let doc = match serde_json::from_str::<serde_json::Value>(&line) {
+                Ok(doc) => doc,
+                Err(err) => {
+                    let err = crate::Error::from(err);
+
+                    self.store.stats().record_index_error();
+                    self.dead_letter(&line, &err);
+
+                    ingested.failed.push((i + 1, err));
+                    continue;
+                },
+            };
+
+            let added = self.build(&doc).and_then(|built| self.add(&built));
+
+            match added {
+                Ok(_) => ingested.indexed += 1,
+                Err(err) => {
+                    self.dead_letter(&doc, &err);
+
+                    ingested.failed.push((i + 1, err));
+                },
+            }
+        }
+
+        self.flush()?;
+
+        Ok(ingested)
+    }
+
+    /**
+    Delete the documents that match a query from every index, and commit.
+
+    Tantivy deletes documents by term, so only queries that match the same
+    documents as a single term are supported, like `level:DEBUG` or `id:5`.
+    Ranges, phrases, negations, and combinations of terms return an error
+    without deleting anything. Indexes that the query doesn't match are
+    left alone.
+    */
+    pub fn delete_by_query(&mut self, query: &str) -> Result<(), crate::Error> {
+        let mut deletes = Vec::new();
+
+        // Check the query against every index before deleting from any of them
+        for (id, index) in self.store.indexes() {
+            let parsed = parse_query(&index, &self.store.config().default_fields, query)?;
+
+            let mut terms = BTreeSet::new();
+            parsed.query_terms(&mut terms);
+
+            self.store.load_latest_searchers(id, &index)?;
+            let searcher = index.searcher();
+
+            let mut matching = CountCollector::default();
+            searcher.search(&*parsed, &mut matching)?;
+
+            if matching.count() == 0 {
+                continue;
+            }
+
+            let term = match (terms.len(), terms.into_iter().next()) {
+                (1, Some(term)) => term,
+                _ => return Err(crate::Error::QueryParse(format!("`{}` can't be deleted because it isn't a single term", query))),
+            };
+
+            // The query could still match different documents than its term,
+            // like when the term is negated
+            let mut by_term = CountCollector::default();
+            searcher.search(&TermQuery::new(term.clone(), IndexRecordOption::Basic), &mut by_term)?;
+
+            if by_term.count() != matching.count() {
+                return Err(crate::Error::QueryParse(format!("`{}` can't be deleted because it doesn't match the same documents as its term", query)));
+            }
+
+            deletes.push((id, term));
+        }
+
+        for (id, term) in deletes {
+            let writer = match self.store.existing_writer(id)? {
+                Some(writer) => writer,
+                None => continue,
+            };
+
+            {
+                let mut writer = lock(&writer);
+
+                writer.delete_term(term);
+                writer.commit()?;
+            }
+
+            self.store.stats().record_commit();
+
+            // Any documents that were pending have been committed along with the delete
+            if let Some(pending) = self.pending.remove(&id) {
+                record_committed(&self.store, id, pending);
+            }
+
+            self.store.record_change(id);
+        }
+
+        Ok(())
+    }
+
+    /**
+    Send a document that failed to index to the dead letter sink, if there is one.
+    */
+    fn dead_letter(&self, doc: impl Serialize, err: &crate::Error) {
+        if let Some(ref sink) = self.dead_letters {
+            sink(serde_json::to_value(doc).unwrap_or(serde_json::Value::Null), err);
+        }
+    }
+
+    /**
+    Build a document using the indexer's options.
+    */
+    fn build(&self, doc: impl Serialize) -> Result<Doc, crate::Error> {
+        Doc::build_with(doc, &self.options).map_err(|err| {
+            self.store.stats().record_index_error();
+            err
+        })
+    }
+
+    /**
+    Write a document to its index without committing it.
+    */
+    fn add(&mut self, doc: &Doc) -> Result<IndexId, crate::Error> {
+        let added = self.write_doc(doc);
+
+        match added {
+            Ok(_) => self.store.stats().record_indexed(),
+            Err(_) => self.store.stats().record_index_error(),
+        }
+
+        added
+    }
+
+    fn write_doc(&mut self, doc: &Doc) -> Result<IndexId, crate::Error> {
+        let mut i = doc.indexable()?;
+
+        if self.widen {
+            self.widen_for(doc, &i)?;
+        }
+
+        // The document's index may have been widened, so it's only known after getting the writer
+        let writer = self.store.get_writer(&mut i)?;
+
+        let index = i.index;
+        let time = doc_time(&i);
+
+        lock(&writer).add_document(i.doc);
+
+        self.pending.entry(index).or_insert_with(Pending::new).add(time);
+
+        Ok(index)
+    }
+
+    fn commit(&mut self, index: IndexId) -> Result<(), crate::Error> {
+        let pending = match self.pending.remove(&index) {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        if let Some(writer) = self.store.writer(index) {
+            lock(&writer).commit()?;
+
+            self.store.stats().record_commit();
+        }
+
+        record_committed(&self.store, index, pending);
+
+        Ok(())
+    }
+
+    fn widen_for(&mut self, doc: &Doc, i: &IndexableDoc) -> Result<(), crate::Error> {
+        // Documents for indexes that have already been widened are rebuilt by the store
+        if self.store.resolve(i.index) == i.index && self.store.index(i.index).is_none() {
+            // The document's shape is new, so see if it can widen an existing index
+            if let Some(narrower) = self.store.find_narrower(doc) {
+                // Pending documents are committed here too so their time range is recorded
+                self.commit(narrower)?;
+                self.store.widen(narrower, i)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/**
+An indexer that can be shared between threads.
+
+Unlike `Indexer`, this doesn't need exclusive access to index documents.
+Writers are shared through the store, so threads indexing documents into
+different indexes don't block each other. Documents are committed according
+to the store's commit policy, unless the indexer is given its own, and the
+documents pending in each index are tracked behind that index's writer.
+Any documents that haven't been committed when the indexer is dropped are
+committed then.
+*/
+pub struct SharedIndexer {
+    store: Store,
+    commit: CommitPolicy,
+    options: Options,
+    widen: bool,
+    dead_letters: Option<DeadLetters>,
+    pending: RwLock<HashMap<IndexId, Arc<Mutex<Option<Pending>>>>>,
+    widening: Mutex<()>,
+}
+
+impl Drop for SharedIndexer {
+    fn drop(&mut self) {
+        // Panicking in drop while already unwinding would abort the process
+        // so failures to commit are ignored
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| self.flush()));
+    }
+}
+
+impl SharedIndexer {
+    pub fn new(store: Store) -> Self {
+        SharedIndexer {
+            options: Options::default().separator(store.config().separator.clone()),
+            commit: store.config().commit,
+            store,
+            widen: false,
+            dead_letters: None,
+            pending: RwLock::new(HashMap::new()),
+            widening: Mutex::new(()),
+        }
+    }
+
+    /**
+    Use the given options when building documents.
+    */
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /**
+    Commit documents using the given policy instead of the store's.
+    */
+    pub fn with_commit_policy(mut self, commit: CommitPolicy) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /**
+    Widen existing indexes instead of creating new ones for documents with extra fields.
+
+    This works the same way as `Indexer::widening`. Only one thread widens an index
+    at a time, and documents for the narrower index that are written while it's
+    being widened are written to the wider one instead.
+    */
+    pub fn widening(mut self) -> Self {
+        self.widen = true;
+        self
+    }
+
+    /**
+    Send documents that fail to index to the given sink.
+    */
+    pub fn with_dead_letters(mut self, sink: impl Fn(serde_json::Value, &crate::Error) + Send + Sync + 'static) -> Self {
+        self.dead_letters = Some(Arc::new(sink));
+        self
+    }
+
+    /**
+    A new exclusive indexer with the same configuration as this one.
+    */
+    pub(crate) fn configured(&self) -> Indexer {
+        Indexer {
+            store: self.store.clone(),
+            pending: HashMap::new(),
+            commit: self.commit,
+            options: self.options.clone(),
+            widen: self.widen,
+            dead_letters: self.dead_letters.clone(),
+        }
+    }
+
+    pub fn index(&self, doc: impl Serialize) -> Result<(), crate::Error> {
+        let indexed = Doc::build_with(&doc, &self.options).and_then(|built| self.index_built(&built));
+
+        if let Err(ref err) = indexed {
+            self.store.stats().record_index_error();
+
+            if let Some(ref sink) = self.dead_letters {
+                sink(serde_json::to_value(&doc).unwrap_or(serde_json::Value::Null), err);
+            }
+        }
+
+        indexed
+    }
+
+    /**
+    Commit any documents that have been written but not committed yet.
+    */
+    pub fn flush(&self) -> Result<(), crate::Error> {
+        let pending = read(&self.pending).keys().cloned().collect::<Vec<_>>();
+
+        for index in pending {
+            self.commit(index, |_| true)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    Commit the documents in any index that have been waiting longer than the batch interval.
+
+    See `Indexer::commit_elapsed`.
+    */
+    pub fn commit_elapsed(&self) -> Result<(), crate::Error> {
+        let commit = self.commit;
+        let pending = read(&self.pending).keys().cloned().collect::<Vec<_>>();
+
+        for index in pending {
+            self.commit(index, |pending| pending.is_elapsed(commit))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn commit_interval(&self) -> Option<Duration> {
+        commit_interval(self.commit)
+    }
+
+    fn index_built(&self, doc: &Doc) -> Result<(), crate::Error> {
+        let mut i = doc.indexable()?;
+
+        if self.widen {
+            self.widen_for(doc, &i)?;
+        }
+
+        loop {
+            let writer = self.store.get_writer(&mut i)?;
+            let mut writer = lock(&writer);
 
-            let mut writer = self.store.get_writer(&i)?;
+            // The index may have been widened while waiting for its writer,
+            // so the document is rebuilt for the wider index
+            if self.store.resolve(i.index) != i.index {
+                continue;
+            }
+
+            let index = i.index;
+            let time = doc_time(&i);
 
             writer.add_document(i.doc);
-            writer.commit()?;
+            self.store.stats().record_indexed();
+
+            let pending = self.pending_in(index);
+            let mut pending = lock(&pending);
+
+            pending.get_or_insert_with(Pending::new).add(time);
+
+            if pending.as_ref().map(|pending| pending.is_due(self.commit)).unwrap_or(false) {
+                let committed = pending.take();
+
+                writer.commit()?;
+                self.store.stats().record_commit();
+
+                drop(pending);
+                drop(writer);
+
+                if let Some(committed) = committed {
+                    record_committed(&self.store, index, committed);
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    /**
+    Get the documents pending in an index, tracking it if it hasn't been written to yet.
+    */
+    fn pending_in(&self, index: IndexId) -> Arc<Mutex<Option<Pending>>> {
+        if let Some(pending) = read(&self.pending).get(&index) {
+            return pending.clone();
+        }
+
+        write(&self.pending).entry(index).or_insert_with(|| Arc::new(Mutex::new(None))).clone()
+    }
+
+    /**
+    Commit the documents pending in an index if they match the predicate.
+
+    The index's writer is locked before its pending documents, the same as when they're written.
+    */
+    fn commit(&self, index: IndexId, due: impl Fn(&Pending) -> bool) -> Result<(), crate::Error> {
+        let pending = match read(&self.pending).get(&index) {
+            Some(pending) => pending.clone(),
+            None => return Ok(()),
+        };
+
+        let committed = match self.store.writer(index) {
+            Some(writer) => {
+                let mut writer = lock(&writer);
+                let mut pending = lock(&pending);
+
+                if !pending.as_ref().map(|pending| due(pending)).unwrap_or(false) {
+                    return Ok(());
+                }
 
-            self.writers.insert(doc.index().to_owned(), writer);
+                let committed = pending.take();
+
+                writer.commit()?;
+                self.store.stats().record_commit();
+
+                committed
+            },
+            // The index was widened, which committed its documents
+            None => lock(&pending).take(),
+        };
+
+        if let Some(committed) = committed {
+            record_committed(&self.store, index, committed);
+        }
+
+        Ok(())
+    }
+
+    fn widen_for(&self, doc: &Doc, i: &IndexableDoc) -> Result<(), crate::Error> {
+        if self.store.resolve(i.index) != i.index || self.store.index(i.index).is_some() {
+            return Ok(());
+        }
+
+        // Widening replaces indexes, so only one thread does it at a time
+        let _widening = lock(&self.widening);
+
+        // Another thread may have created or widened the index since we checked
+        if self.store.resolve(i.index) != i.index || self.store.index(i.index).is_some() {
+            return Ok(());
+        }
+
+        if let Some(narrower) = self.store.find_narrower(doc) {
+            // Pending documents are committed here too so their time range is recorded
+            self.commit(narrower, |_| true)?;
+            self.store.widen(narrower, i)?;
         }
 
         Ok(())
     }
 }
+
+impl Indexer {
+    /**
+    A new shared indexer with the same configuration as this one.
+    */
+    pub(crate) fn shared(&self) -> SharedIndexer {
+        SharedIndexer {
+            store: self.store.clone(),
+            commit: self.commit,
+            options: self.options.clone(),
+            widen: self.widen,
+            dead_letters: self.dead_letters.clone(),
+            pending: RwLock::new(HashMap::new()),
+            widening: Mutex::new(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            Mutex,
+            mpsc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use serde_derive::Serialize;
+    use serde_json::json;
+
+    use crate::{
+        config::{
+            CommitPolicy,
+            Config,
+        },
+        schema::Path,
+        searcher::{
+            QueryBuilder,
+            Searcher,
+        },
+        temp::TempDir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn widening_replaces_narrower_index() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).widening();
+
+        indexer.index(json!({ "a": "base" })).expect("failed to index");
+        indexer.index(json!({ "a": "superset", "b": "extra" })).expect("failed to index");
+        indexer.index(json!({ "a": "base again" })).expect("failed to index");
+
+        assert_eq!(1, store.indexes().into_iter().count());
+
+        let searcher = Searcher::new(store);
+        let docs = searcher.all(10).expect("failed to search").into_iter().count();
+
+        assert_eq!(3, docs);
+    }
+
+    #[test]
+    fn indexers_that_do_not_widen_write_to_widened_indexes() {
+        let store = Store::new();
+        let mut widening = Indexer::new(store.clone()).widening();
+
+        let base = json!({ "a": "base" });
+        let narrow = Doc::build(&base).expect("failed to build doc").index();
+
+        widening.index(&base).expect("failed to index");
+        widening.index(json!({ "a": "superset", "b": "extra" })).expect("failed to index");
+
+        let mut plain = Indexer::new(store.clone());
+        plain.index(json!({ "a": "base again" })).expect("failed to index");
+
+        assert_eq!(1, store.indexes().into_iter().count());
+
+        let searcher = Searcher::new(store);
+        assert_eq!(3, searcher.search_in(narrow, "*", 10).expect("failed to search").into_iter().count());
+        assert_eq!(1, searcher.search_in(narrow, "a:again", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn widening_copies_fields_that_are_only_fast_fields() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone())
+            .with_options(Options::new().source(true).compress_source(true))
+            .widening();
+
+        let base = json!({ "a": "base", "f": 1.5 });
+
+        indexer.index(&base).expect("failed to index");
+        indexer.index(json!({ "a": "superset", "b": "extra", "f": 2.5 })).expect("failed to index");
+
+        assert_eq!(1, store.indexes().into_iter().count());
+
+        let searcher = Searcher::new(store);
+
+        let docs: Vec<serde_json::Value> = searcher
+            .search("a:base", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(json!([1.5]), docs[0]["f"]);
+
+        let sources: Vec<String> = searcher
+            .search_source("a:base", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(vec![serde_json::to_string(&base).expect("failed to serialize")], sources);
+    }
+
+    #[test]
+    fn widening_copies_docs_pending_in_other_indexers() {
+        let store = Store::new();
+
+        let mut batched = Indexer::new(store.clone()).with_commit_policy(CommitPolicy::Batch {
+            docs: 100,
+            interval: Duration::from_secs(60),
+        });
+        let mut widening = Indexer::new(store.clone()).widening();
+
+        batched.index(json!({ "a": "pending" })).expect("failed to index");
+        widening.index(json!({ "a": "superset", "b": "extra" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn widening_only_replaces_indexes_with_the_same_kinds_of_fields() {
+        #[derive(Serialize)]
+        struct Narrower {
+            a: Path<&'static str>,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).widening();
+
+        // A path and a string are both text fields, but they're tokenized differently
+        indexer.index(Narrower { a: Path("a/b") }).expect("failed to index");
+        indexer.index(json!({ "a": "a/b", "b": "extra" })).expect("failed to index");
+
+        assert_eq!(2, store.indexes().into_iter().count());
+    }
+
+    #[test]
+    fn shared_indexer_only_blocks_per_index() {
+        let store = Store::new();
+        let indexer = SharedIndexer::new(store.clone());
+
+        let a = json!({ "a": "text" });
+        indexer.index(&a).expect("failed to index");
+
+        // Hold the writer for `a` while indexing into another index
+        let a_writer = store.get_writer(&mut Doc::build(&a).expect("failed to build doc").indexable().expect("failed to build doc")).expect("failed to get writer");
+        let a_lock = a_writer.lock().expect("poisoned writer");
+
+        let (indexed, wait) = mpsc::channel();
+        thread::spawn(move || {
+            indexer.index(json!({ "b": "text" })).expect("failed to index");
+            indexed.send(()).expect("failed to send");
+        });
+
+        wait.recv_timeout(Duration::from_secs(5)).expect("indexing was blocked by another index");
+
+        drop(a_lock);
+    }
+
+    #[test]
+    fn shared_indexer_commits_in_batches() {
+        let store = Store::new();
+        let searcher = Searcher::new(store.clone());
+
+        let indexer = SharedIndexer::new(store.clone())
+            .with_commit_policy(CommitPolicy::Batch { docs: 2, interval: Duration::from_secs(60) });
+
+        indexer.index(json!({ "a": "first" })).expect("failed to index");
+        assert_eq!(0, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "a": "second" })).expect("failed to index");
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "a": "third" })).expect("failed to index");
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.flush().expect("failed to flush");
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+
+        assert_eq!(3, store.stats().docs_indexed());
+        assert_eq!(2, store.stats().commits());
+    }
+
+    #[test]
+    fn config_sets_heap_size_and_commit_policy() {
+        let store = Store::with_config(Config::new()
+            .heap_size(10_000_000)
+            .commit(CommitPolicy::Batch { docs: 2, interval: Duration::from_secs(60) }));
+
+        assert_eq!(10_000_000, store.config().heap_size);
+
+        let mut indexer = Indexer::new(store.clone());
+        let searcher = Searcher::new(store.clone());
+
+        indexer.index(json!({ "a": "first" })).expect("failed to index");
+        assert_eq!(0, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "a": "second" })).expect("failed to index");
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "a": "third" })).expect("failed to index");
+        indexer.flush().expect("failed to flush");
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn commit_elapsed_commits_batches_past_their_interval() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_commit_policy(CommitPolicy::Batch {
+            docs: 100,
+            interval: Duration::from_millis(50),
+        });
+        let searcher = Searcher::new(store.clone());
+
+        indexer.index(json!({ "a": "first" })).expect("failed to index");
+
+        indexer.commit_elapsed().expect("failed to commit");
+        assert_eq!(0, searcher.all(10).expect("failed to search").into_iter().count());
+
+        thread::sleep(Duration::from_millis(100));
+
+        indexer.commit_elapsed().expect("failed to commit");
+        assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn batched_commits_create_fewer_segments() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_commit_policy(CommitPolicy::Batch {
+            docs: 100,
+            interval: Duration::from_secs(60),
+        });
+
+        for n in 0..1000 {
+            indexer.index(json!({ "msg": "a record", "n": n })).expect("failed to index");
+        }
+
+        indexer.flush().expect("failed to flush");
+
+        let (_, index) = store.indexes().into_iter().next().expect("missing index");
+        let segments = index.searchable_segment_ids().expect("failed to get segments").len();
+
+        assert!(segments <= 10, "expected at most 10 segments, got {}", segments);
+
+        let searcher = Searcher::new(store);
+        assert_eq!(1000, searcher.all(1000).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn indexers_share_a_writer_per_index() {
+        let store = Store::new();
+
+        let mut a = Indexer::new(store.clone());
+        let mut b = Indexer::new(store.clone());
+
+        a.index(json!({ "a": "first" })).expect("failed to index");
+        b.index(json!({ "a": "second" })).expect("failed to index");
+        a.index(json!({ "a": "third" })).expect("failed to index");
+
+        let mut i = Doc::build(json!({ "a": "any" })).expect("failed to build doc").indexable().expect("failed to build doc");
+        let first = store.get_writer(&mut i).expect("failed to get writer");
+        let second = store.get_writer(&mut i).expect("failed to get writer");
+
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let searcher = Searcher::new(store);
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn index_all_commits_every_doc_once() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let docs = (0..100u64).map(|n| if n % 2 == 0 {
+            json!({ "msg": "even", "n": n })
+        } else {
+            json!({ "msg": "odd" })
+        });
+
+        assert_eq!(100, indexer.index_all(docs).expect("failed to index"));
+
+        let searcher = Searcher::new(store.clone());
+        assert_eq!(100, searcher.all(0).expect("failed to search").into_iter().count());
+
+        // Each index only gets a single segment from its one commit
+        for (_, index) in store.indexes() {
+            assert_eq!(1, index.searchable_segment_ids().expect("failed to get segments").len());
+        }
+    }
+
+    #[test]
+    fn index_all_reports_how_many_docs_were_indexed() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let docs = vec![
+            json!({ "msg": "first" }),
+            json!({ "msg": "second" }),
+            json!({ "msg": ["conflicting", 1] }),
+            json!({ "msg": "fourth" }),
+        ];
+
+        match indexer.index_all(docs) {
+            Err(crate::Error::Partial { indexed, .. }) => assert_eq!(2, indexed),
+            r => panic!("expected a partial error, got {:?}", r),
+        }
+
+        let searcher = Searcher::new(store);
+        assert_eq!(2, searcher.all(0).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn index_ndjson_skips_blank_and_invalid_lines() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let ndjson: &[u8] = br#"{ "msg": "first", "level": "INFO" }
+
+{ "msg": "second", "level": "WARN" }
+not json
+{ "msg": "third", "level": "INFO" }
+"#;
+
+        let ingested = indexer.index_ndjson(ndjson).expect("failed to ingest");
+
+        assert_eq!(3, ingested.indexed);
+        assert_eq!(vec![4], ingested.failed.iter().map(|(line, _)| *line).collect::<Vec<_>>());
+
+        let searcher = Searcher::new(store);
+        assert_eq!(2, searcher.search("level:INFO", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn delete_by_query_removes_matching_docs() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for level in &["DEBUG", "INFO", "DEBUG", "WARN", "DEBUG"] {
+            indexer.index(json!({ "level": level, "msg": "a record" })).expect("failed to index");
+        }
+
+        match indexer.delete_by_query("level:DEBUG OR level:WARN") {
+            Err(crate::Error::QueryParse(_)) => (),
+            r => panic!("expected a query error, got {:?}", r),
+        }
+
+        indexer.delete_by_query("level:DEBUG").expect("failed to delete");
+
+        let searcher = Searcher::new(store);
+        assert_eq!(2, searcher.all(0).expect("failed to search").into_iter().count());
+        assert_eq!(0, searcher.search("level:DEBUG", 0).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn dropping_the_indexer_commits_pending_docs() {
+        let dir = TempDir::new();
+
+        {
+            let store = Store::on_disk(dir.path()).expect("failed to open store");
+            let mut indexer = Indexer::new(store.clone()).with_commit_policy(CommitPolicy::Batch {
+                docs: 100,
+                interval: Duration::from_secs(60),
+            });
+
+            indexer.index(json!({ "msg": "first" })).expect("failed to index");
+            indexer.index(json!({ "msg": "second" })).expect("failed to index");
+
+            drop(indexer);
+        }
+
+        let store = Store::on_disk(dir.path()).expect("failed to open store");
+
+        let searcher = Searcher::new(store);
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn denied_fields_are_not_searchable() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(Options::new().deny_field("secret"));
+
+        indexer.index(json!({ "msg": "a record", "secret": "hunter2" })).expect("failed to index");
+
+        let searcher = Searcher::new(store.clone());
+
+        assert_eq!(1, searcher.search("msg:record", 10).expect("failed to search").into_iter().count());
+        assert_eq!(0, searcher.search_query(&QueryBuilder::term("secret", "hunter2"), 10).expect("failed to search").into_iter().count());
+        assert!(store.indexes().into_iter().all(|(_, index)| index.schema().get_field("secret").is_none()));
+    }
+
+    #[test]
+    fn failed_docs_are_sent_to_dead_letters() {
+        let store = Store::new();
+
+        let dead = Arc::new(Mutex::new(Vec::new()));
+        let mut indexer = Indexer::new(store).with_dead_letters({
+            let dead = dead.clone();
+            move |doc, err| dead.lock().expect("poisoned").push((doc, err.to_string()))
+        });
+
+        indexer.index(json!({ "msg": "fine" })).expect("failed to index");
+
+        let conflicting = json!({ "msg": ["conflicting", 1] });
+        assert!(indexer.index(&conflicting).is_err());
+
+        let dead = dead.lock().expect("poisoned");
+
+        assert_eq!(1, dead.len());
+        assert_eq!(conflicting, dead[0].0);
+        assert!(dead[0].1.contains("conflicting types"));
+    }
+}
@@ -0,0 +1,321 @@
+/*!
+A `tracing` layer that indexes events.
+
+This is an alternative front-end to the `log` implementation in `logger`.
+Events are captured into the same records as log records, so they're
+flattened and indexed the same way.
+*/
+
+use std::{
+    fmt,
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::{
+        atomic::Ordering,
+        Arc,
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use log::{
+    Level,
+    LevelFilter,
+};
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    span::{
+        Attributes,
+        Id,
+        Record,
+    },
+    Event,
+    Subscriber,
+};
+
+use tracing_subscriber::{
+    layer::{
+        Context,
+        Layer,
+    },
+    registry::LookupSpan,
+};
+
+use crate::{
+    index::Indexer,
+    logger::{
+        severity,
+        Failures,
+        IndexableRecord,
+        OnIndexError,
+    },
+    schema::Date,
+    store::Store,
+    sync::lock,
+};
+
+/**
+A `tracing_subscriber::Layer` that writes events to `tantivy`.
+
+The fields of each event are indexed under `props`, and the fields of
+the spans the event happened in are indexed under `spans`, by span name.
+*/
+pub struct TantivyLayer {
+    indexer: Mutex<Indexer>,
+    filter: LevelFilter,
+    failures: Failures,
+}
+
+/**
+The fields recorded on a span so far.
+*/
+struct SpanFields(Map<String, Value>);
+
+/**
+A visitor that collects fields into a JSON map.
+*/
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl TantivyLayer {
+    pub fn new(store: Store) -> Self {
+        TantivyLayer {
+            filter: store.config().level,
+            indexer: Mutex::new(Indexer::new(store)),
+            failures: Failures::new(),
+        }
+    }
+
+    /**
+    Only index events at or above the given level.
+
+    This defaults to the level in the store's config.
+    */
+    pub fn with_level(mut self, filter: LevelFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /**
+    Choose what to do when an event can't be indexed.
+
+    This works the same way as `Logger::on_index_error`, and defaults to `OnIndexError::Count`.
+    */
+    pub fn on_index_error(self, policy: OnIndexError) -> Self {
+        lock(&self.failures.handling).0 = policy;
+        self
+    }
+
+    /**
+    Send events that can't be indexed to the given sink.
+
+    The sink is given the event's record as JSON along with the error.
+    */
+    pub fn with_dead_letters(self, sink: impl Fn(serde_json::Value, &crate::Error) + Send + Sync + 'static) -> Self {
+        lock(&self.failures.handling).1 = Some(Arc::new(sink));
+        self
+    }
+
+    /**
+    The number of events that couldn't be indexed.
+    */
+    pub fn index_errors(&self) -> usize {
+        self.failures.count.load(Ordering::Relaxed)
+    }
+
+    /**
+    Commit any events that haven't been committed yet.
+    */
+    pub fn flush(&self) -> Result<(), crate::Error> {
+        lock(&self.indexer).flush()
+    }
+}
+
+impl<S> Layer<S> for TantivyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(&mut FieldVisitor(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        let metadata = event.metadata();
+        let level = to_level(*metadata.level());
+
+        if level > self.filter {
+            return;
+        }
+
+        let mut props = Map::new();
+        event.record(&mut FieldVisitor(&mut props));
+
+        let msg = match props.remove("message") {
+            Some(Value::String(msg)) => msg,
+            Some(msg) => msg.to_string(),
+            None => String::new(),
+        };
+
+        let mut spans = Map::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    spans.insert(span.name().to_owned(), Value::Object(fields.0.clone()));
+                }
+            }
+        }
+
+        let record = IndexableRecord {
+            timestamp: Date(SystemTime::now()),
+            level,
+            severity: severity(level),
+            target: metadata.target(),
+            module_path: metadata.module_path(),
+            file: metadata.file(),
+            line: metadata.line(),
+            msg,
+            signature: None,
+            spans: if !spans.is_empty() { Some(spans) } else { None },
+            props,
+        };
+
+        // Like the logger, a record that panics is dropped instead of poisoning the indexer
+        let indexed = {
+            let mut indexer = lock(&self.indexer);
+
+            panic::catch_unwind(AssertUnwindSafe(|| indexer.index(&record)))
+        };
+
+        match indexed {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => self.failures.fail(&record, &err),
+            Err(_) => self.failures.panicked(),
+        }
+    }
+}
+
+impl<'a> Visit for FieldVisitor<'a> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_owned(), Value::from(format!("{:?}", value)));
+    }
+}
+
+fn to_level(level: tracing::Level) -> Level {
+    match level {
+        tracing::Level::ERROR => Level::Error,
+        tracing::Level::WARN => Level::Warn,
+        tracing::Level::INFO => Level::Info,
+        tracing::Level::DEBUG => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Dispatch;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{
+        searcher::{
+            QueryBuilder,
+            Searcher,
+        },
+        temp::TempDir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn events_are_indexed_with_their_spans() {
+        let store = Store::new();
+        let subscriber = tracing_subscriber::registry().with(TantivyLayer::new(store.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", id = 42);
+            let _guard = span.enter();
+
+            tracing::info!(user = "alice", "handled a request");
+        });
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .search_query(&QueryBuilder::term("props.user", "alice"), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(Some("handled a request"), docs[0]["msg"][0].as_str());
+        assert_eq!(Some("INFO"), docs[0]["level"][0].as_str());
+        assert_eq!(Some(42), docs[0]["spans.request.id"][0].as_i64());
+    }
+
+    #[test]
+    fn events_that_fail_to_index_are_counted() {
+        let dir = TempDir::new();
+
+        let store = Store::open_read_only(dir.path()).expect("failed to open store");
+
+        let dead = Arc::new(Mutex::new(Vec::new()));
+        let layer = TantivyLayer::new(store).with_dead_letters({
+            let dead = dead.clone();
+            move |record, _| dead.lock().expect("poisoned").push(record)
+        });
+
+        // Read-only stores can't create an index for the event
+        let dispatch = Dispatch::new(tracing_subscriber::registry().with(layer));
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!("an event that can't be written");
+        });
+
+        let layer = dispatch.downcast_ref::<TantivyLayer>().expect("missing layer");
+
+        assert_eq!(1, layer.index_errors());
+        assert_eq!(1, dead.lock().expect("poisoned").len());
+    }
+}
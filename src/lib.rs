@@ -0,0 +1,57 @@
+/*!
+Structured logging into tantivy.
+
+Records are flattened into documents and written to an index based on
+their shape, so records with the same fields share an index. The indexes
+can then be searched with tantivy queries.
+
+```
+use serde_json::json;
+
+use tantivy_log::{
+    Indexer,
+    Searcher,
+    Store,
+};
+
+# fn main() -> Result<(), tantivy_log::Error> {
+let store = Store::new();
+
+let mut indexer = Indexer::new(store.clone());
+indexer.index(json!({ "msg": "a structured record", "id": 1 }))?;
+
+let searcher = Searcher::new(store);
+let docs = searcher.search("msg:structured", 10)?.into_iter().count();
+
+assert_eq!(1, docs);
+# Ok(())
+# }
+```
+*/
+
+pub mod config;
+pub mod error;
+pub mod index;
+#[cfg(feature = "layer")]
+pub mod layer;
+pub mod logger;
+pub mod schema;
+pub mod searcher;
+pub mod stats;
+pub mod store;
+mod sync;
+#[cfg(test)]
+mod temp;
+pub mod tokenizer;
+
+pub use crate::{
+    config::Config,
+    error::Error,
+    index::Indexer,
+    schema::{
+        Doc,
+        Value,
+    },
+    searcher::Searcher,
+    store::Store,
+};
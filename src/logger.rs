@@ -1,4 +1,34 @@
-use std::sync::Mutex;
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{
+        Hash,
+        Hasher,
+    },
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        mpsc::{
+            self,
+            RecvTimeoutError,
+            SyncSender,
+            TrySendError,
+        },
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+    },
+    thread,
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
 
 use log::{
     set_boxed_logger,
@@ -13,61 +43,1532 @@ use log::{
     },
 };
 
-use serde_derive::Serialize;
+use serde::{
+    Serialize,
+    Serializer,
+    ser::SerializeMap,
+};
 
 use crate::{
-    index::Indexer,
+    index::{
+        DeadLetters,
+        Indexer,
+        SharedIndexer,
+    },
+    config::Config,
+    schema::{
+        Date,
+        Doc,
+    },
     store::Store,
+    sync::lock,
 };
 
+/**
+The field that holds the formatted message of a record.
+*/
+pub const MSG_FIELD: &'static str = "msg";
+
+/**
+The field that holds the numeric severity of a record.
+*/
+pub const SEVERITY_FIELD: &'static str = "__severity";
+
+/**
+The field that holds the signature of a record.
+*/
+pub const SIGNATURE_FIELD: &'static str = "__signature";
+
 /**
 An implementation of `Log` that writes to `tantivy`.
 
-This logger will flush after each event. This isn't really ideal,
-but since we only log to a RAM drive it's not a big deal.
+Records are committed according to the store's commit policy. By default
+that's after each event. This isn't really ideal, but since we only log to
+a RAM drive it's not a big deal. Flushing the logger commits any records
+that haven't been committed yet.
+
+Dropping the logger also flushes it. A logger installed with `init` is never
+dropped though, so call `log::logger().flush()` before exiting.
 */
 pub struct Logger {
-    indexer: Mutex<Indexer>,
+    store: Store,
+    indexer: Indexing,
+    sampling: Sampling,
+    signatures: bool,
+    level: LevelHandle,
+    fields: RecordFields,
+    failures: Arc<Failures>,
+}
+
+/**
+What to do when a record can't be indexed.
+
+Logging never returns an error, so failures are handled by the logger.
+Records that fail are sent to the logger's dead letter sink regardless
+of the policy.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnIndexError {
+    /**
+    Drop the record without a trace.
+    */
+    Ignore,
+    /**
+    Drop the record and count it in `Logger::index_errors`.
+    */
+    Count,
+    /**
+    Count the record and print the error to stderr.
+    */
+    Print,
+}
+
+/**
+Records that couldn't be indexed.
+
+This is shared with the background worker, so failures on either thread
+are handled the same way, whichever order the logger was configured in.
+The `tracing` layer handles its failures the same way too.
+*/
+pub(crate) struct Failures {
+    pub(crate) count: AtomicUsize,
+    pub(crate) handling: Mutex<(OnIndexError, Option<DeadLetters>)>,
+}
+
+impl Failures {
+    pub(crate) fn new() -> Self {
+        Failures {
+            count: AtomicUsize::new(0),
+            handling: Mutex::new((OnIndexError::Count, None)),
+        }
+    }
+
+    pub(crate) fn fail(&self, record: impl Serialize, err: &crate::Error) {
+        let (policy, dead_letters) = lock(&self.handling).clone();
+
+        match policy {
+            OnIndexError::Ignore => (),
+            OnIndexError::Count => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+            },
+            OnIndexError::Print => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+
+                eprintln!("failed to index a log record: {}", err);
+            },
+        }
+
+        if let Some(sink) = dead_letters {
+            sink(serde_json::to_value(record).unwrap_or(serde_json::Value::Null), err);
+        }
+    }
+
+    /**
+    Committing failed, so any records that were pending may have been lost.
+
+    There's no single record to send to the dead letter sink, so the failure is only counted.
+    */
+    pub(crate) fn commit_failed(&self, err: &crate::Error) {
+        let (policy, _) = *lock(&self.handling);
+
+        match policy {
+            OnIndexError::Ignore => (),
+            OnIndexError::Count => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+            },
+            OnIndexError::Print => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+
+                eprintln!("failed to commit log records: {}", err);
+            },
+        }
+    }
+
+    /**
+    A record that panicked can't be serialized again safely, so it's only counted.
+    */
+    pub(crate) fn panicked(&self) {
+        let (policy, _) = *lock(&self.handling);
+
+        match policy {
+            OnIndexError::Ignore => (),
+            OnIndexError::Count => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+            },
+            OnIndexError::Print => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+
+                eprintln!("failed to index a log record: indexing panicked");
+            },
+        }
+    }
+}
+
+/**
+A handle to a logger's level that can be changed at runtime.
+
+Changing the level also sets the max level for the `log` crate, so records
+at the new level aren't filtered out before they reach the logger.
+*/
+#[derive(Clone)]
+pub struct LevelHandle(Arc<AtomicUsize>);
+
+impl LevelHandle {
+    fn new(filter: LevelFilter) -> Self {
+        LevelHandle(Arc::new(AtomicUsize::new(filter as usize)))
+    }
+
+    /**
+    Get the current level.
+    */
+    pub fn get(&self) -> LevelFilter {
+        match self.0.load(Ordering::Relaxed) {
+            0 => LevelFilter::Off,
+            1 => LevelFilter::Error,
+            2 => LevelFilter::Warn,
+            3 => LevelFilter::Info,
+            4 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /**
+    Only index records at or above the given level.
+    */
+    pub fn set(&self, filter: LevelFilter) {
+        self.0.store(filter as usize, Ordering::Relaxed);
+
+        set_max_level(filter);
+    }
+}
+
+enum Indexing {
+    Exclusive(Arc<Mutex<Indexer>>),
+    Shared(Arc<SharedIndexer>),
+    Background(Worker),
+}
+
+/**
+What to do with a record when the background queue is full.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueFull {
+    /**
+    Block the logging thread until there's room in the queue.
+    */
+    Block,
+    /**
+    Drop the record.
+    */
+    Drop,
+}
+
+/**
+A worker thread that indexes documents sent to it.
+*/
+struct Worker {
+    sender: Mutex<SyncSender<Message>>,
+    config: Indexer,
+    capacity: usize,
+    full: QueueFull,
+    dropped: AtomicUsize,
+}
+
+enum Message {
+    Doc(Doc),
+    Flush(mpsc::Sender<()>),
+}
+
+impl Worker {
+    fn spawn(indexer: Indexer, capacity: usize, full: QueueFull, failures: Arc<Failures>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+
+        // Records are built on the logging thread, so the worker keeps a copy of the
+        // indexer's configuration around for building them and for configuring new workers
+        let config = indexer.configured();
+
+        // Failed records go to the logger's dead letters as well as any the indexer already had
+        let mut indexer = indexer.with_dead_letters({
+            let failures = failures.clone();
+            let dead_letters = config.dead_letters();
+            move |record, err| {
+                if let Some(ref sink) = dead_letters {
+                    sink(record.clone(), err);
+                }
+
+                failures.fail(record, err);
+            }
+        });
+
+        // The worker runs until the logger is dropped
+        // Any documents still in the queue are indexed and committed before it stops
+        thread::spawn(move || {
+            loop {
+                // When commits are batched, the worker wakes up at least once per interval
+                // so the last records in a batch don't wait for another one to arrive
+                let msg = match indexer.commit_interval() {
+                    Some(interval) => match receiver.recv_timeout(interval) {
+                        Ok(msg) => msg,
+                        Err(RecvTimeoutError::Timeout) => {
+                            if let Err(err) = indexer.commit_elapsed() {
+                                failures.commit_failed(&err);
+                            }
+
+                            continue;
+                        },
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match receiver.recv() {
+                        Ok(msg) => msg,
+                        Err(_) => break,
+                    },
+                };
+
+                match msg {
+                    Message::Doc(doc) => {
+                        // Errors are handled by the indexer's dead letter sink
+                        if panic::catch_unwind(AssertUnwindSafe(|| indexer.index_doc(doc))).is_err() {
+                            failures.panicked();
+                        }
+                    },
+                    Message::Flush(done) => {
+                        if let Err(err) = indexer.flush() {
+                            failures.commit_failed(&err);
+                        }
+
+                        let _ = done.send(());
+                    },
+                }
+            }
+
+            if let Err(err) = indexer.flush() {
+                failures.commit_failed(&err);
+            }
+        });
+
+        Worker {
+            sender: Mutex::new(sender),
+            config,
+            capacity,
+            full,
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn send(&self, doc: Doc) {
+        let sender = lock(&self.sender);
+
+        match self.full {
+            QueueFull::Block => {
+                let _ = sender.send(Message::Doc(doc));
+            },
+            QueueFull::Drop => {
+                if let Err(TrySendError::Full(_)) = sender.try_send(Message::Doc(doc)) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+        }
+    }
+
+    fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+
+        // Flushing always waits for room in the queue, even if records are dropped
+        // The worker handles messages in order, so everything sent before the flush
+        // has been indexed by the time it's acknowledged
+        let sent = lock(&self.sender).send(Message::Flush(done));
+
+        if sent.is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+/**
+Commit batches in an indexer on the logging thread once their interval has passed.
+
+Records are only committed when the next one is indexed otherwise, so the last
+records in a batch could stay invisible indefinitely. The ticker thread only
+holds a weak reference to the indexer, so it stops once the logger is dropped.
+*/
+fn tick<T>(indexer: &Arc<T>, interval: Option<Duration>, commit_elapsed: fn(&T) -> Result<(), crate::Error>, failures: Arc<Failures>)
+where
+    T: Send + Sync + 'static,
+{
+    let interval = match interval {
+        Some(interval) => interval,
+        None => return,
+    };
+
+    let indexer = Arc::downgrade(indexer);
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        match indexer.upgrade() {
+            Some(indexer) => {
+                if let Err(err) = commit_elapsed(&indexer) {
+                    failures.commit_failed(&err);
+                }
+            },
+            None => break,
+        }
+    });
+}
+
+fn exclusive(indexer: Indexer, failures: Arc<Failures>) -> Indexing {
+    let interval = indexer.commit_interval();
+
+    let indexer = Arc::new(Mutex::new(indexer));
+    tick(&indexer, interval, |indexer| lock(indexer).commit_elapsed(), failures);
+
+    Indexing::Exclusive(indexer)
+}
+
+fn shared(indexer: SharedIndexer, failures: Arc<Failures>) -> Indexing {
+    let interval = indexer.commit_interval();
+
+    let indexer = Arc::new(indexer);
+    tick(&indexer, interval, SharedIndexer::commit_elapsed, failures);
+
+    Indexing::Shared(indexer)
+}
+
+impl Logger {
+    pub fn new(store: Store) -> Self {
+        Logger::with_sampling(store, Sampling::keep_all())
+    }
+
+    pub fn with_sampling(store: Store, sampling: Sampling) -> Self {
+        let failures = Arc::new(Failures::new());
+
+        Logger {
+            indexer: exclusive(Indexer::new(store.clone()), failures.clone()),
+            level: LevelHandle::new(store.config().level),
+            fields: RecordFields::default(),
+            store,
+            sampling,
+            signatures: false,
+            failures,
+        }
+    }
+
+    /**
+    Index records without a global lock.
+
+    Normally, all records are indexed through a single lock. When sharded,
+    threads logging records into different indexes don't block each other.
+    Records are still committed in batches according to the indexer's commit policy.
+    */
+    pub fn sharded(mut self) -> Self {
+        let indexer = self.configured_indexer().shared();
+
+        self.indexer = shared(indexer, self.failures.clone());
+        self
+    }
+
+    /**
+    Index records on a background thread.
+
+    Records are flattened on the logging thread, then sent to a worker through a
+    queue that holds up to `capacity` records. Adding documents and committing them
+    happens on the worker, so logging doesn't wait for indexing. When the queue is
+    full, records are either dropped or the logging thread blocks until there's room.
+
+    Flushing the logger waits for the queue to drain and commits everything in it.
+    The worker uses the same configuration as the logger's indexer, including any
+    given to `with_indexer`.
+    */
+    pub fn in_background(mut self, capacity: usize, full: QueueFull) -> Self {
+        let indexer = self.configured_indexer();
+
+        self.indexer = Indexing::Background(Worker::spawn(indexer, capacity, full, self.failures.clone()));
+        self
+    }
+
+    /**
+    Index records with the given indexer.
+
+    The indexer's options, commit policy, widening, and dead letters are used for
+    every record, whether they're indexed on the logging thread, sharded, or in the background.
+    */
+    pub fn with_indexer(mut self, indexer: Indexer) -> Self {
+        self.indexer = match self.indexer {
+            Indexing::Exclusive(_) => exclusive(indexer, self.failures.clone()),
+            Indexing::Shared(_) => shared(indexer.shared(), self.failures.clone()),
+            Indexing::Background(ref worker) => Indexing::Background(Worker::spawn(indexer, worker.capacity, worker.full, self.failures.clone())),
+        };
+        self
+    }
+
+    /**
+    A new indexer with the same configuration as the one records are indexed with.
+    */
+    fn configured_indexer(&self) -> Indexer {
+        match self.indexer {
+            Indexing::Exclusive(ref indexer) => lock(indexer).configured(),
+            Indexing::Shared(ref indexer) => indexer.configured(),
+            Indexing::Background(ref worker) => worker.config.configured(),
+        }
+    }
+
+    /**
+    Index a `__signature` field with each record.
+
+    The signature identifies the shape of a log statement, so records
+    logged by the same statement with the same set of properties share
+    a signature even when the values in them differ.
+    */
+    pub fn with_signatures(mut self) -> Self {
+        self.signatures = true;
+        self
+    }
+
+    /**
+    Choose what to do when a record can't be indexed.
+
+    This defaults to `OnIndexError::Count`.
+    */
+    pub fn on_index_error(self, policy: OnIndexError) -> Self {
+        lock(&self.failures.handling).0 = policy;
+        self
+    }
+
+    /**
+    Send records that can't be indexed to the given sink.
+
+    The sink is given the record as JSON along with the error. Records that
+    panic while being indexed aren't sent to the sink.
+    */
+    pub fn with_dead_letters(self, sink: impl Fn(serde_json::Value, &crate::Error) + Send + Sync + 'static) -> Self {
+        lock(&self.failures.handling).1 = Some(Arc::new(sink));
+        self
+    }
+
+    /**
+    Use the given names for the fields of indexed records.
+    */
+    pub fn with_record_fields(mut self, fields: RecordFields) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /**
+    Only index records at or above the given level.
+
+    This defaults to the level in the store's config.
+    */
+    pub fn with_level(mut self, filter: LevelFilter) -> Self {
+        self.level = LevelHandle::new(filter);
+        self
+    }
+
+    /**
+    Change the level of records to index.
+
+    This can be called after the logger has been installed.
+    */
+    pub fn set_level(&self, filter: LevelFilter) {
+        self.level.set(filter);
+    }
+
+    /**
+    Get a handle that can change the logger's level after it's been installed.
+    */
+    pub fn level(&self) -> LevelHandle {
+        self.level.clone()
+    }
+
+    /**
+    The number of records dropped by sampling.
+    */
+    pub fn dropped(&self) -> usize {
+        self.sampling.dropped()
+    }
+
+    /**
+    The number of records that couldn't be indexed.
+
+    Commits that fail, whether on the logging thread, the background worker, or
+    while committing a batch on a timer, are counted once each. Failures are
+    only counted if the logger's policy isn't `OnIndexError::Ignore`.
+    */
+    pub fn index_errors(&self) -> usize {
+        self.failures.count.load(Ordering::Relaxed)
+    }
+
+    /**
+    The number of records dropped because the background queue was full.
+    */
+    pub fn overflowed(&self) -> usize {
+        match self.indexer {
+            Indexing::Background(ref worker) => worker.dropped.load(Ordering::Relaxed),
+            _ => 0,
+        }
+    }
+
+    /**
+    Index a single record.
+
+    Indexing happens inside a `catch_unwind` boundary, so a record that
+    panics while being flattened or indexed is dropped instead of poisoning
+    the indexer and breaking all future logging. Records that fail are
+    handled according to the logger's `OnIndexError` policy.
+    */
+    fn index(&self, record: impl Serialize) {
+        let indexed = match self.indexer {
+            Indexing::Exclusive(ref indexer) => {
+                let mut indexer = lock(&indexer);
+
+                panic::catch_unwind(AssertUnwindSafe(|| indexer.index(&record)))
+            },
+            Indexing::Shared(ref indexer) => {
+                panic::catch_unwind(AssertUnwindSafe(|| indexer.index(&record)))
+            },
+            Indexing::Background(ref worker) => {
+                // Records borrow from the caller, so they're flattened before being sent
+                let doc = panic::catch_unwind(AssertUnwindSafe(|| Doc::build_with(&record, worker.config.options())));
+
+                doc.map(|doc| doc.map(|doc| worker.send(doc)))
+            },
+        };
+
+        match indexed {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => self.failures.fail(&record, &err),
+            Err(_) => self.failures.panicked(),
+        }
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| Log::flush(self)));
+    }
 }
 
 impl Log for Logger {
     fn log(&self, record: &Record) {
-        let mut indexer = self.indexer.lock().expect("indexer poisoned");
+        if !self.enabled(record.metadata()) {
+            return;
+        }
 
-        let record = IndexableRecord {
-            level: record.level(),
-            msg: format!("{}", record.args()),
-            props: record.key_values().into_map(),
-        };
+        if !self.sampling.sample(record.level()) {
+            return;
+        }
 
-        let _ = indexer.index(record);
+        self.index(IndexableRecord::from_record(record, self.signatures).with_fields(&self.fields));
     }
 
-    fn enabled(&self, _: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level.get()
     }
 
     fn flush(&self) {
+        match self.indexer {
+            Indexing::Exclusive(ref indexer) => {
+                let mut indexer = lock(&indexer);
 
+                if let Err(err) = indexer.flush() {
+                    self.failures.commit_failed(&err);
+                }
+            },
+            Indexing::Shared(ref indexer) => {
+                if let Err(err) = indexer.flush() {
+                    self.failures.commit_failed(&err);
+                }
+            },
+            Indexing::Background(ref worker) => worker.flush(),
+        }
     }
 }
 
 /**
 A log record that can be serialized and indexed.
 
-It's built from a standard `log::Record`.
+It's built from a standard `log::Record`, or a `tracing` event. The callsite
+fields are skipped when they're missing rather than being indexed as `None`.
+Records are serialized with the default `RecordFields`.
+*/
+pub(crate) struct IndexableRecord<'a, KVS> {
+    pub(crate) timestamp: Date,
+    pub(crate) level: Level,
+    pub(crate) severity: u64,
+    pub(crate) target: &'a str,
+    pub(crate) module_path: Option<&'a str>,
+    pub(crate) file: Option<&'a str>,
+    pub(crate) line: Option<u32>,
+    pub(crate) msg: String,
+    pub(crate) signature: Option<String>,
+    pub(crate) spans: Option<serde_json::Map<String, serde_json::Value>>,
+    pub(crate) props: KVS,
+}
+
+/**
+The names of the fields a record is indexed with.
+
+The names should be distinct from each other. Searches that order by time
+or highlight messages look for the default `timestamp` and `msg` fields, so
+they won't find renamed ones.
+
+Properties are always nested in their own field, so a property named `level`
+or `msg` is indexed as `props.level` or `props.msg`, and can't clash with the
+fields of the record itself. If the field for properties is empty, or has the
+same name as another field of the record, then it's suffixed with `_` until
+it's unique.
 */
-#[derive(Serialize)]
-struct IndexableRecord<KVS> {
-    level: Level,
-    msg: String,
-    props: KVS,
+#[derive(Debug, Clone)]
+pub struct RecordFields {
+    timestamp: Cow<'static, str>,
+    level: Cow<'static, str>,
+    target: Cow<'static, str>,
+    msg: Cow<'static, str>,
+    props: Cow<'static, str>,
 }
 
-pub fn init(store: Store) {
-    set_boxed_logger(Box::new(Logger {
-        indexer: Mutex::new(Indexer::new(store)),
-    })).expect("failed to init logger");
+impl Default for RecordFields {
+    fn default() -> Self {
+        RecordFields {
+            timestamp: Cow::Borrowed("timestamp"),
+            level: Cow::Borrowed("level"),
+            target: Cow::Borrowed("target"),
+            msg: Cow::Borrowed(MSG_FIELD),
+            props: Cow::Borrowed("props"),
+        }
+    }
+}
+
+impl RecordFields {
+    pub fn new() -> Self {
+        RecordFields::default()
+    }
+
+    /**
+    Set the name of the field for the time a record was logged.
+    */
+    pub fn timestamp(mut self, name: impl Into<String>) -> Self {
+        self.timestamp = Cow::Owned(name.into());
+        self
+    }
+
+    /**
+    Set the name of the field for the level of a record.
+    */
+    pub fn level(mut self, name: impl Into<String>) -> Self {
+        self.level = Cow::Owned(name.into());
+        self
+    }
+
+    /**
+    Set the name of the field for the target of a record.
+    */
+    pub fn target(mut self, name: impl Into<String>) -> Self {
+        self.target = Cow::Owned(name.into());
+        self
+    }
+
+    /**
+    Set the name of the field for the formatted message of a record.
+    */
+    pub fn message(mut self, name: impl Into<String>) -> Self {
+        self.msg = Cow::Owned(name.into());
+        self
+    }
+
+    /**
+    Set the name of the field that properties are nested in.
+    */
+    pub fn props(mut self, name: impl Into<String>) -> Self {
+        self.props = Cow::Owned(name.into());
+        self
+    }
+
+    /**
+    Whether a name is used by one of the other fields of a record.
+    */
+    fn is_reserved(&self, name: &str) -> bool {
+        name == self.timestamp
+            || name == self.level
+            || name == self.target
+            || name == self.msg
+            || RESERVED_FIELDS.contains(&name)
+    }
+
+    /**
+    Get the name of the field that properties are nested in.
+
+    An empty name would flatten properties into the record, so it's
+    treated like any other name that collides with the record's fields.
+    */
+    fn props_field(&self) -> Cow<str> {
+        if !self.props.is_empty() && !self.is_reserved(&self.props) {
+            return Cow::Borrowed(&*self.props);
+        }
+
+        let mut name = format!("{}_", self.props);
+        while self.is_reserved(&name) {
+            name.push('_');
+        }
+
+        Cow::Owned(name)
+    }
+}
+
+/**
+The fields of a record that can't be renamed.
+*/
+const RESERVED_FIELDS: &[&str] = &[SEVERITY_FIELD, SIGNATURE_FIELD, "module_path", "file", "line", "spans"];
+
+/**
+A record that's serialized with the given field names.
+*/
+pub(crate) struct NamedRecord<'r, 'a, KVS> {
+    record: &'r IndexableRecord<'a, KVS>,
+    fields: &'r RecordFields,
+}
+
+impl<'a, KVS> IndexableRecord<'a, KVS> {
+    /**
+    Serialize the record using the given field names.
+    */
+    pub(crate) fn with_fields<'r>(&'r self, fields: &'r RecordFields) -> NamedRecord<'r, 'a, KVS> {
+        NamedRecord {
+            record: self,
+            fields,
+        }
+    }
+}
+
+impl<'a, KVS> Serialize for IndexableRecord<'a, KVS>
+where
+    KVS: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.with_fields(&RecordFields::default()).serialize(serializer)
+    }
+}
+
+impl<'r, 'a, KVS> Serialize for NamedRecord<'r, 'a, KVS>
+where
+    KVS: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let record = self.record;
+        let fields = self.fields;
+
+        let mut map = serializer.serialize_map(None)?;
+
+        map.serialize_entry(&*fields.timestamp, &record.timestamp)?;
+        map.serialize_entry(&*fields.level, &record.level)?;
+        map.serialize_entry(SEVERITY_FIELD, &record.severity)?;
+        map.serialize_entry(&*fields.target, record.target)?;
+
+        if let Some(module_path) = record.module_path {
+            map.serialize_entry("module_path", module_path)?;
+        }
+
+        if let Some(file) = record.file {
+            map.serialize_entry("file", file)?;
+        }
+
+        if let Some(line) = record.line {
+            map.serialize_entry("line", &line)?;
+        }
+
+        map.serialize_entry(&*fields.msg, &record.msg)?;
+
+        if let Some(ref signature) = record.signature {
+            map.serialize_entry(SIGNATURE_FIELD, signature)?;
+        }
 
-    set_max_level(LevelFilter::Info);
+        if let Some(ref spans) = record.spans {
+            map.serialize_entry("spans", spans)?;
+        }
+
+        map.serialize_entry(&*fields.props_field(), &record.props)?;
+
+        map.end()
+    }
+}
+
+impl<'a> IndexableRecord<'a, ()> {
+    /**
+    Capture a standard `log::Record`.
+
+    The record's key-values are captured as its properties.
+    */
+    pub(crate) fn from_record(record: &'a Record<'a>, signatures: bool) -> IndexableRecord<'a, impl Serialize + 'a> {
+        let props = record.key_values().into_map();
+
+        let signature = if signatures {
+            Some(signature(record, &props))
+        } else {
+            None
+        };
+
+        IndexableRecord {
+            timestamp: Date(SystemTime::now()),
+            level: record.level(),
+            severity: severity(record.level()),
+            target: record.target(),
+            module_path: record.module_path(),
+            file: record.file(),
+            line: record.line(),
+            msg: format!("{}", record.args()),
+            signature,
+            spans: None,
+            props,
+        }
+    }
+}
+
+/**
+An implementation of `Log` that captures records in memory instead of indexing them.
+
+Records are captured the same way the `Logger` captures them, then serialized
+into JSON values. That makes it cheap to check what an application logs in tests,
+without needing a store.
+*/
+#[cfg(any(test, feature = "capture"))]
+pub struct CapturingLogger {
+    records: Captured,
+    signatures: bool,
+    filter: LevelFilter,
+}
+
+/**
+A handle to the records captured by a `CapturingLogger`.
+
+The handle can be kept after the logger is installed.
+*/
+#[cfg(any(test, feature = "capture"))]
+#[derive(Clone, Default)]
+pub struct Captured(std::sync::Arc<Mutex<Vec<serde_json::Value>>>);
+
+#[cfg(any(test, feature = "capture"))]
+impl Captured {
+    /**
+    Take all of the records captured so far.
+    */
+    pub fn drain(&self) -> Vec<serde_json::Value> {
+        lock(&self.0).drain(..).collect()
+    }
+}
+
+#[cfg(any(test, feature = "capture"))]
+impl CapturingLogger {
+    pub fn new() -> Self {
+        CapturingLogger {
+            records: Captured::default(),
+            signatures: false,
+            filter: LevelFilter::Trace,
+        }
+    }
+
+    /**
+    Capture a `__signature` field with each record.
+    */
+    pub fn with_signatures(mut self) -> Self {
+        self.signatures = true;
+        self
+    }
+
+    /**
+    Only capture records at or above the given level.
+
+    This defaults to capturing everything.
+    */
+    pub fn with_level(mut self, filter: LevelFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /**
+    Get a handle to the captured records.
+    */
+    pub fn records(&self) -> Captured {
+        self.records.clone()
+    }
+
+    /**
+    Take all of the records captured so far.
+    */
+    pub fn drain(&self) -> Vec<serde_json::Value> {
+        self.records.drain()
+    }
+}
+
+#[cfg(any(test, feature = "capture"))]
+impl Log for CapturingLogger {
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Ok(record) = serde_json::to_value(IndexableRecord::from_record(record, self.signatures)) {
+            lock(&self.records.0).push(record);
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter
+    }
+
+    fn flush(&self) {}
+}
+
+/**
+Get the severity of a level.
+
+Levels are indexed as text, which can't be filtered by range, so each record
+also carries its severity as a number. More severe levels have larger numbers,
+so `Error` is `5` and `Trace` is `1`.
+*/
+pub fn severity(level: Level) -> u64 {
+    (Level::Trace as u64 + 1) - level as u64
+}
+
+/**
+Compute a signature for the shape of a record.
+
+The rendered message isn't a stable template, so the log statement is
+identified by its level and callsite instead. The names of the record's
+properties are included, but not their values.
+*/
+fn signature(record: &Record, props: &impl Serialize) -> String {
+    let mut hasher = DefaultHasher::new();
+
+    (record.level() as usize).hash(&mut hasher);
+    record.module_path().hash(&mut hasher);
+    record.file().hash(&mut hasher);
+    record.line().hash(&mut hasher);
+
+    if let Ok(serde_json::Value::Object(props)) = serde_json::to_value(props) {
+        // The keys of a `serde_json::Map` are already sorted
+        for key in props.keys() {
+            key.hash(&mut hasher);
+        }
+    }
+
+    format!("{:016x}", hasher.finish())
+}
+
+/**
+A sampling policy for records.
+
+Each level keeps 1 in `n` records, where `n` defaults to `1` so every record
+is kept. Sampling is deterministic: the first record at a level is kept, then
+every `n`th one after it. Records are dropped before they're serialized or
+indexed.
+*/
+pub struct Sampling {
+    rates: [usize; 5],
+    seen: [AtomicUsize; 5],
+    dropped: AtomicUsize,
+}
+
+impl Sampling {
+    pub fn keep_all() -> Self {
+        Sampling {
+            rates: [1; 5],
+            seen: [
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+            ],
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+    Keep 1 in `n` records at the given level.
+
+    A rate of `0` is treated as `1`.
+    */
+    pub fn keep_one_in(mut self, level: Level, n: usize) -> Self {
+        self.rates[Sampling::slot(level)] = n.max(1);
+        self
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn sample(&self, level: Level) -> bool {
+        let slot = Sampling::slot(level);
+
+        let rate = self.rates[slot];
+        if rate == 1 {
+            return true;
+        }
+
+        let seen = self.seen[slot].fetch_add(1, Ordering::Relaxed);
+        if seen % rate == 0 {
+            true
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    fn slot(level: Level) -> usize {
+        level as usize - 1
+    }
+}
+
+/**
+Log to the store.
+
+The returned handle can be used to change the level of records to index.
+*/
+pub fn init(store: Store) -> LevelHandle {
+    init_with_sampling(store, Sampling::keep_all())
+}
+
+pub fn init_with_sampling(store: Store, sampling: Sampling) -> LevelHandle {
+    init_with_logger(Logger::with_sampling(store, sampling))
+}
+
+/**
+Log to a configured logger.
+
+The max level for the `log` crate is set to the logger's level filter.
+*/
+pub fn init_with_logger(logger: Logger) -> LevelHandle {
+    let level = logger.level();
+
+    set_boxed_logger(Box::new(logger)).expect("failed to init logger");
+
+    set_max_level(level.get());
+
+    level
+}
+
+/**
+Create a store with the given config and log to it.
+
+The store is returned so it can be searched.
+*/
+pub fn init_with_config(config: Config) -> Store {
+    let store = Store::with_config(config);
+
+    init(store.clone());
+
+    store
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        config::CommitPolicy,
+        schema::Options,
+        searcher::{QueryBuilder, Searcher},
+    };
+
+    use super::*;
+
+    #[test]
+    fn panicking_record_does_not_break_logging() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone());
+
+        struct Panics;
+
+        impl Serialize for Panics {
+            fn serialize<S>(&self, _: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                panic!("failed to serialize")
+            }
+        }
+
+        logger.index(Panics);
+
+        // Conflicting types for the same field fail while building the schema
+        logger.index(json!({
+            "a": [1, "b"]
+        }));
+
+        logger.index(json!({
+            "b": "ok"
+        }));
+
+        let searcher = Searcher::new(store);
+        let docs = searcher.search("b:ok", 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, docs);
+    }
+
+    #[test]
+    fn sampling_drops_info_and_keeps_warn() {
+        let store = Store::new();
+        let logger = Logger::with_sampling(store.clone(), Sampling::keep_all().keep_one_in(Level::Info, 2));
+
+        for _ in 0..10 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("an info record")).build());
+        }
+
+        for _ in 0..3 {
+            logger.log(&Record::builder().level(Level::Warn).args(format_args!("a warn record")).build());
+        }
+
+        let searcher = Searcher::new(store);
+        let infos = searcher.search("level:INFO", 100).expect("failed to search").into_iter().count();
+        let warns = searcher.search("level:WARN", 100).expect("failed to search").into_iter().count();
+
+        assert_eq!(5, infos);
+        assert_eq!(3, warns);
+        assert_eq!(5, logger.dropped());
+    }
+
+    #[test]
+    fn signatures_depend_on_callsite_and_prop_keys() {
+        let signature_of = |props: serde_json::Value| signature(&Record::builder()
+            .level(Level::Info)
+            .args(format_args!("a record"))
+            .module_path(Some("tantivy_log::logger"))
+            .file(Some("src/logger.rs"))
+            .line(Some(42))
+            .build(), &props);
+
+        let a = signature_of(json!({ "a": 1, "b": "x" }));
+        let b = signature_of(json!({ "b": "y", "a": 2 }));
+        let c = signature_of(json!({ "a": 1 }));
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn records_can_be_found_by_signature() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone()).with_signatures();
+
+        let record_at = |line| Record::builder()
+            .level(Level::Info)
+            .args(format_args!("a record"))
+            .module_path(Some("tantivy_log::logger"))
+            .file(Some("src/logger.rs"))
+            .line(Some(line))
+            .build();
+
+        logger.log(&record_at(42));
+        logger.log(&record_at(43));
+
+        let record = record_at(42);
+        let expected = signature(&record, &record.key_values().into_map());
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .search_query(&QueryBuilder::term(SIGNATURE_FIELD, expected.clone()), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid doc"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(json!(expected), docs[0][SIGNATURE_FIELD]);
+        assert_eq!(json!(42), docs[0]["line"]);
+    }
+
+    #[test]
+    fn records_below_the_level_filter_are_not_indexed() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone()).with_level(LevelFilter::Warn);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("an info record")).build());
+        logger.log(&Record::builder().level(Level::Warn).args(format_args!("a warn record")).build());
+
+        let searcher = Searcher::new(store);
+        let infos = searcher.search("level:INFO", 10).expect("failed to search").into_iter().count();
+        let warns = searcher.search("level:WARN", 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(0, infos);
+        assert_eq!(1, warns);
+    }
+
+    #[test]
+    fn records_capture_their_callsite() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone());
+
+        logger.log(&Record::builder()
+            .level(Level::Info)
+            .args(format_args!("a record"))
+            .target("tantivy_log")
+            .module_path(Some("tantivy_log::logger"))
+            .file(Some("src/logger.rs"))
+            .line(Some(42))
+            .build());
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .search("module_path:\"tantivy_log::logger\"", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(Some("src/logger.rs"), hits[0]["file"][0].as_str());
+        assert_eq!(Some(42), hits[0]["line"][0].as_u64());
+        assert!(hits[0]["timestamp"][0].is_i64());
+    }
+
+    #[test]
+    fn flush_commits_pending_records() {
+        let config = Config::new().commit(CommitPolicy::Batch { docs: 100, interval: Duration::from_secs(60) });
+
+        for sharded in vec![false, true] {
+            let store = Store::with_config(config.clone());
+            let logger = Logger::new(store.clone());
+            let logger = if sharded { logger.sharded() } else { logger };
+
+            for _ in 0..2 {
+                logger.log(&Record::builder().level(Level::Info).args(format_args!("a record")).build());
+            }
+
+            let searcher = Searcher::new(store.clone());
+
+            assert_eq!(0, searcher.all(10).expect("failed to search").into_iter().count());
+
+            logger.flush();
+
+            assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+            assert_eq!(1, store.stats().commits());
+        }
+    }
+
+    #[test]
+    fn batched_records_are_committed_after_the_interval() {
+        let config = Config::new().commit(CommitPolicy::Batch { docs: 100, interval: Duration::from_millis(50) });
+
+        for (background, sharded) in vec![(false, false), (true, false), (false, true)] {
+            let store = Store::with_config(config.clone());
+            let logger = Logger::new(store.clone());
+            let logger = if background { logger.in_background(10, QueueFull::Block) } else { logger };
+            let logger = if sharded { logger.sharded() } else { logger };
+
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("a record")).build());
+
+            thread::sleep(Duration::from_millis(300));
+
+            let searcher = Searcher::new(store);
+            assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+        }
+    }
+
+    #[test]
+    fn searching_with_levels_excludes_less_severe_records() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone());
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("an info record")).build());
+        logger.log(&Record::builder().level(Level::Warn).args(format_args!("a warn record")).build());
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("an error record")).build());
+
+        let searcher = Searcher::new(store);
+        let levels: Vec<String> = searcher
+            .search_with_levels("msg:record", 10, Level::Warn)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| {
+                let doc: serde_json::Value = serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json");
+
+                doc["level"][0].as_str().expect("missing level").to_owned()
+            })
+            .collect();
+
+        assert_eq!(2, levels.len());
+        assert!(!levels.contains(&"INFO".to_owned()));
+    }
+
+    #[test]
+    fn background_records_are_searchable_after_flush() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone()).in_background(16, QueueFull::Block);
+
+        for n in 0..500 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("record {}", n)).build());
+        }
+
+        logger.flush();
+
+        let searcher = Searcher::new(store);
+
+        assert_eq!(500, searcher.all(1000).expect("failed to search").into_iter().count());
+        assert_eq!(0, logger.overflowed());
+    }
+
+    #[test]
+    fn background_records_use_the_loggers_indexer_config() {
+        let store = Store::new();
+        let indexer = Indexer::new(store.clone())
+            .with_options(Options::new().keyword_field(MSG_FIELD))
+            .with_commit_policy(CommitPolicy::Batch { docs: 100, interval: Duration::from_secs(60) });
+
+        let logger = Logger::new(store.clone()).with_indexer(indexer).in_background(16, QueueFull::Block);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("a record")).build());
+
+        thread::sleep(Duration::from_millis(100));
+
+        let searcher = Searcher::new(store);
+
+        // The store commits each doc, but the indexer given to the logger batches them
+        assert_eq!(0, searcher.all(10).expect("failed to search").into_iter().count());
+
+        logger.flush();
+
+        assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+
+        // The message is indexed as a keyword, so it isn't matched by a single word
+        assert_eq!(0, searcher.search("msg:record", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn capturing_logger_captures_records() {
+        let logger = CapturingLogger::new().with_level(LevelFilter::Info);
+        let records = logger.records();
+
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("an error record")).build());
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("an info record")).build());
+        logger.log(&Record::builder().level(Level::Debug).args(format_args!("a debug record")).build());
+
+        let captured = records.drain();
+
+        assert_eq!(2, captured.len());
+        assert_eq!("ERROR", captured[0]["level"]);
+        assert_eq!("an error record", captured[0]["msg"]);
+        assert_eq!("INFO", captured[1]["level"]);
+
+        assert_eq!(0, logger.drain().len());
+    }
+
+    #[test]
+    fn level_can_change_at_runtime() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone()).with_level(LevelFilter::Info);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("an info record")).build());
+        logger.log(&Record::builder().level(Level::Debug).args(format_args!("a debug record")).build());
+
+        logger.level().set(LevelFilter::Debug);
+
+        logger.log(&Record::builder().level(Level::Debug).args(format_args!("a debug record")).build());
+
+        let searcher = Searcher::new(store);
+        let infos = searcher.search("level:INFO", 10).expect("failed to search").into_iter().count();
+        let debugs = searcher.search("level:DEBUG", 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, infos);
+        assert_eq!(1, debugs);
+        assert_eq!(LevelFilter::Debug, logger.level().get());
+    }
+
+    #[test]
+    fn record_fields_can_be_renamed() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone()).with_record_fields(RecordFields::new().message("message").level("severity"));
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("a structured record")).build());
+
+        let searcher = Searcher::new(store);
+
+        assert_eq!(1, searcher.search("message:structured AND severity:INFO", 10).expect("failed to search").into_iter().count());
+        assert_eq!(0, searcher.has_field("msg", 10).expect("failed to search").into_iter().count());
+        assert_eq!(0, searcher.has_field("level", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn props_do_not_clash_with_record_fields() {
+        let store = Store::new();
+        let logger = Logger::new(store.clone());
+
+        let record = IndexableRecord {
+            timestamp: Date(SystemTime::now()),
+            level: Level::Info,
+            severity: severity(Level::Info),
+            target: "tantivy_log",
+            module_path: None,
+            file: None,
+            line: None,
+            msg: "a record".to_owned(),
+            signature: None,
+            spans: None,
+            props: json!({ "level": "custom", "msg": "a property" }),
+        };
+
+        logger.index(&record);
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher.search_as("level:INFO", 10).expect("failed to search");
+
+        assert_eq!(1, docs.len());
+        assert_eq!("INFO", docs[0]["level"]);
+        assert_eq!("a record", docs[0]["msg"]);
+        assert_eq!("custom", docs[0]["props"]["level"]);
+        assert_eq!("a property", docs[0]["props"]["msg"]);
+
+        // Props can't be renamed onto another field
+        let json = |fields: RecordFields| serde_json::to_value(record.with_fields(&fields)).expect("failed to serialize");
+
+        assert_eq!("custom", json(RecordFields::new().props("level"))["level_"]["level"]);
+        assert_eq!("custom", json(RecordFields::new().props(""))["_"]["level"]);
+        assert_eq!("INFO", json(RecordFields::new().props(""))["level"]);
+    }
+
+    #[test]
+    fn index_errors_are_counted() {
+        let store = Store::new();
+
+        let dead = Arc::new(Mutex::new(Vec::new()));
+        let logger = Logger::new(store.clone()).with_dead_letters({
+            let dead = dead.clone();
+            move |record, _| dead.lock().expect("poisoned").push(record)
+        });
+
+        let record = |props| IndexableRecord {
+            timestamp: Date(SystemTime::now()),
+            level: Level::Info,
+            severity: severity(Level::Info),
+            target: "tantivy_log",
+            module_path: None,
+            file: None,
+            line: None,
+            msg: "a record".to_owned(),
+            signature: None,
+            spans: None,
+            props,
+        };
+
+        logger.index(&record(json!({ "a": 1 })));
+        assert_eq!(0, logger.index_errors());
+
+        // Conflicting types for the same field fail while building the schema
+        logger.index(&record(json!({ "a": [1, "b"] })));
+        assert_eq!(1, logger.index_errors());
+
+        let dead = dead.lock().expect("poisoned");
+        assert_eq!(1, dead.len());
+        assert_eq!(json!([1, "b"]), dead[0]["props"]["a"]);
+
+        let ignoring = Logger::new(store).on_index_error(OnIndexError::Ignore);
+
+        ignoring.index(&record(json!({ "a": [1, "b"] })));
+        assert_eq!(0, ignoring.index_errors());
+    }
 }
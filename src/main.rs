@@ -1,4 +1,4 @@
-/**
+/*!
 An example of a structured `log::Log` implementation that logs to tantivy.
 
 This is a *really* simple logger, it's not designed to be efficient or
@@ -7,12 +7,6 @@ and pass different tantivy queries as the first argument to this binary
 to see how records can be logged.
 */
 
-mod logger;
-mod searcher;
-mod index;
-mod schema;
-mod store;
-
 use log::{
     log,
     properties,
@@ -20,13 +14,13 @@ use log::{
 
 use serde_json::json;
 
-use crate::{
-    store::Store,
-    searcher::Searcher,
+use tantivy_log::{
+    logger,
+    Store,
+    Searcher,
+    schema::Path,
 };
 
-pub type Error = failure::Error;
-
 /**
 Log some structured records to the store.
 */
@@ -37,8 +31,8 @@ fn do_some_logging(store: &Store) {
         id: 1,
         #[log(display)]
         name: "log",
-        #[log(path)]
-        path: "./monkey-path",
+        #[log(serde)]
+        path: Path("./monkey-path"),
     });
 
     log!(log::Level::Warn, msg: { "A structured {name}", name = "log" }, kvs: {
@@ -57,11 +51,18 @@ fn do_some_logging(store: &Store) {
 
 /**
 Query the store for some records.
+
+Without a query, every record is returned.
 */
-fn do_some_querying(store: &Store, query: &str) {
+fn do_some_querying(store: &Store, query: Option<&str>) {
     let searcher = Searcher::new(store.clone());
 
-    for doc in searcher.search(&query, 10).expect("failed to search") {
+    let docs: Vec<_> = match query {
+        Some(query) => searcher.search(query, 10).expect("failed to search").into_iter().collect(),
+        None => searcher.all(10).expect("failed to search").into_iter().collect(),
+    };
+
+    for doc in docs {
         println!("{}", doc.expect("failed to read doc"));
     }
 }
@@ -71,9 +72,12 @@ fn main() {
 
     do_some_logging(&store);
 
-    let query = std::env::args().skip(1).next().unwrap_or_else(|| "*".to_owned());
+    let query = std::env::args().skip(1).next();
 
-    println!("querying for `{}`", query);
+    match query {
+        Some(ref query) => println!("querying for `{}`", query),
+        None => println!("querying for everything"),
+    }
 
-    do_some_querying(&store, &query);
+    do_some_querying(&store, query.as_ref().map(|query| &**query));
 }
@@ -1,29 +1,292 @@
 use std::{
     fmt,
     error,
+    io::{
+        Read,
+        Write,
+    },
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
     hash::{
         Hash,
         Hasher,
     },
     collections::{
+        BTreeSet,
         VecDeque,
         HashMap,
+        HashSet,
         hash_map,
     },
 };
 
 use serde::ser::{self, Error, Serializer, Serialize, Impossible};
-use tantivy::schema::{
-    Schema,
-    SchemaBuilder,
-    FAST,
-    TEXT,
-    STRING,
-    STORED,
-    Document,
+use flate2::{
+    read::DeflateDecoder,
+    write::DeflateEncoder,
+    Compression,
+};
+use tantivy::{
+    DocId,
+    SegmentReader,
+    TantivyError,
+    fastfield::{
+        FastFieldReader,
+        MultiValueIntFastFieldReader,
+    },
+    schema::{
+        Cardinality,
+        Field,
+        FieldEntry,
+        FieldType,
+        IntOptions,
+        Schema,
+        SchemaBuilder,
+        IndexRecordOption,
+        TextFieldIndexing,
+        TextOptions,
+        FAST,
+        INDEXED,
+        TEXT,
+        STORED,
+        Document,
+    },
+};
+
+use crate::{
+    index::IndexId,
+    tokenizer::{
+        KEYWORD_TOKENIZER,
+        LOWERCASE_KEYWORD_TOKENIZER,
+        PATH_TOKENIZER,
+    },
 };
 
-use crate::index::IndexId;
+/**
+The reserved field that holds the original serialized document.
+*/
+pub const SOURCE_FIELD: &'static str = "_source";
+
+/**
+Compress a document's source with raw DEFLATE.
+*/
+fn compress_source(source: &str) -> Result<Vec<u8>, crate::Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(source.as_bytes())?;
+
+    Ok(encoder.finish()?)
+}
+
+/**
+Decompress a document's source that was compressed with `Options::compress_source`.
+*/
+pub fn decompress_source(compressed: &[u8]) -> Result<String, crate::Error> {
+    let mut source = String::new();
+    DeflateDecoder::new(compressed).read_to_string(&mut source)?;
+
+    Ok(source)
+}
+
+const JSON_NEWTYPE: &'static str = "__tantivy_log_json";
+const PATH_NEWTYPE: &'static str = "__tantivy_log_path";
+const DATE_NEWTYPE: &'static str = "__tantivy_log_date";
+
+/**
+A hint to keep a value as a single opaque JSON field.
+
+Values are normally flattened into a field per leaf. Wrapping a value in
+`Json` serializes it into a single JSON string field instead, which keeps
+large or irregular nested blobs from fragmenting the schema. Serializers
+other than the field collector see the inner value transparently.
+
+This is a wrapper rather than a `#[log(json)]` attribute because attributes
+on properties are interpreted by the `log` macros, which only know how to
+capture values, not how this crate should index them.
+*/
+pub struct Json<T>(pub T);
+
+impl<T> Serialize for Json<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(JSON_NEWTYPE, &self.0)
+    }
+}
+
+/**
+A hint to index a value as a path.
+
+Paths are kept whole instead of being split into words, so they can be
+matched exactly. Each parent directory in the path is also indexed, so
+searching for a directory will match any paths beneath it.
+*/
+pub struct Path<T>(pub T);
+
+impl<T> Serialize for Path<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(PATH_NEWTYPE, &self.0)
+    }
+}
+
+/**
+A hint to index a value as a date.
+
+Dates are indexed as signed millisecond timestamps since the unix epoch,
+so they can be filtered by range. Serializers other than the field
+collector see the timestamp as a plain number.
+*/
+pub struct Date(pub SystemTime);
+
+impl Serialize for Date {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(DATE_NEWTYPE, &to_millis(self.0))
+    }
+}
+
+/**
+Convert a point in time into milliseconds since the unix epoch.
+*/
+pub fn to_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() * 1000 + since.subsec_millis() as u64) as i64,
+        Err(before) => {
+            let before = before.duration();
+
+            -((before.as_secs() * 1000 + before.subsec_millis() as u64) as i64)
+        },
+    }
+}
+
+/**
+Parse an RFC3339 date, like `2020-01-01T00:00:00Z`, into milliseconds since the unix epoch.
+
+Fractions of a second beyond milliseconds are truncated. If the value isn't
+an RFC3339 date then `None` is returned.
+*/
+pub fn parse_rfc3339(value: &str) -> Option<i64> {
+    let bytes = value.as_bytes();
+
+    let digits = |start: usize, len: usize| -> Option<i64> {
+        let digits = value.get(start..start + len)?;
+
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+
+    match bytes[10] {
+        b'T' | b't' | b' ' => (),
+        _ => return None,
+    }
+
+    let (year, month, day) = (digits(0, 4)?, digits(5, 2)?, digits(8, 2)?);
+    let (hour, minute, second) = (digits(11, 2)?, digits(14, 2)?, digits(17, 2)?);
+
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut i = 19;
+    let mut millis = 0;
+
+    if bytes[i] == b'.' {
+        let len = bytes[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+        if len == 0 {
+            return None;
+        }
+
+        // Only the first three digits are milliseconds
+        for n in 0..3 {
+            millis *= 10;
+
+            if n < len {
+                millis += (bytes[i + 1 + n] - b'0') as i64;
+            }
+        }
+
+        i += 1 + len;
+    }
+
+    let offset = match bytes.get(i) {
+        Some(b'Z') | Some(b'z') if bytes.len() == i + 1 => 0,
+        Some(sign) if (*sign == b'+' || *sign == b'-') && bytes.len() == i + 6 && bytes[i + 3] == b':' => {
+            let (hours, minutes) = (digits(i + 1, 2)?, digits(i + 4, 2)?);
+
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+
+            let offset = (hours * 60 + minutes) * 60;
+
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        },
+        _ => return None,
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second - offset;
+
+    Some(seconds * 1_000 + millis)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/**
+The number of days between the unix epoch and a date in the proleptic Gregorian calendar.
+*/
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/**
+Convert milliseconds since the unix epoch back into a point in time.
+*/
+pub fn from_millis(millis: i64) -> SystemTime {
+    if millis >= 0 {
+        UNIX_EPOCH + Duration::from_millis(millis as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_millis(millis.wrapping_neg() as u64)
+    }
+}
 
 /**
 A document that can be indexed in tantivy.
@@ -31,9 +294,14 @@ A document that can be indexed in tantivy.
 The document is built by collecting and flattening the fields of some
 serializable type. The document carries an index that is built of a
 hash of its fields.
+
+Arrays aren't given a field per element. Each element is another value
+for the same field, so arrays of different lengths share an index and
+all of their elements are returned in search results.
 */
 pub struct Doc {
     index: IndexId,
+    separator: String,
     fields: Vec<(String, Value)>,
 }
 
@@ -43,115 +311,799 @@ pub struct IndexableDoc {
     pub doc: Document,
 }
 
+/**
+Options for building a document.
+*/
+#[derive(Debug, Clone)]
+pub struct Options {
+    empty: EmptyPolicy,
+    empty_fields: HashMap<String, EmptyPolicy>,
+    keyword_fields: HashSet<String>,
+    field_hints: FieldHints,
+    case_insensitive_fields: HashSet<String>,
+    case_insensitive_keywords: bool,
+    allowed_fields: Option<HashSet<String>>,
+    denied_fields: HashSet<String>,
+    separator: String,
+    source: bool,
+    compress_source: bool,
+    max_depth: usize,
+}
+
+/**
+Hints for how the values of fields should be indexed, by their flattened name.
+*/
+pub type FieldHints = HashMap<String, FieldHint>;
+
+/**
+A hint for how the values of a field should be indexed.
+
+Without a hint, the way a value is indexed is inferred from its type.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldHint {
+    /**
+    Split values into words, so they can be searched for by any word in them.
+
+    This is the default for strings. Numbers and paths are indexed as text.
+    */
+    FullText,
+    /**
+    Keep values whole, so they're only matched exactly or by their prefix.
+
+    This is the same as `Options::keyword_field`.
+    */
+    Raw,
+    /**
+    Index values as paths, so they're also matched by each of their parents.
+    */
+    Prefix,
+    /**
+    Index values as signed integers, so they can be searched by range.
+
+    Every value of the field is indexed as the same type, so the schema doesn't
+    depend on which values a document happens to have. Strings are parsed as
+    integers, and numbers are converted if they're whole and fit in an `i64`.
+    Documents with any other values in the field fail to build.
+    */
+    Numeric,
+}
+
+impl FieldHint {
+    fn apply(&self, field: &str, value: Value) -> Result<Value, Invalid> {
+        let not_an_integer = |ty: &str| Invalid::custom(format_args!("the field `{}` is numeric, but has a `{}` value that isn't an integer", field, ty));
+
+        let value = match (*self, value) {
+            (FieldHint::FullText, Value::Path(v)) => Value::Str(v),
+            (FieldHint::FullText, Value::Signed(v)) => Value::Str(v.to_string()),
+            (FieldHint::FullText, Value::Unsigned(v)) => Value::Str(v.to_string()),
+            (FieldHint::FullText, Value::Float(v)) => Value::Str(v.to_string()),
+            (FieldHint::Raw, Value::Str(v)) | (FieldHint::Raw, Value::Path(v)) => Value::Keyword(v),
+            (FieldHint::Prefix, Value::Str(v)) => Value::Path(v),
+            (FieldHint::Numeric, value) => match value {
+                Value::Signed(v) => Value::Signed(v),
+                Value::Unsigned(v) if v <= i64::max_value() as u64 => Value::Signed(v as i64),
+                Value::Float(v) if v.fract() == 0.0 && v >= i64::min_value() as f64 && v < i64::max_value() as f64 => Value::Signed(v as i64),
+                Value::Str(v) => match v.parse() {
+                    Ok(v) => Value::Signed(v),
+                    Err(_) => return Err(not_an_integer("str")),
+                },
+                Value::None => Value::None,
+                value => return Err(not_an_integer(value.ty())),
+            },
+            (_, value) => value,
+        };
+
+        Ok(value)
+    }
+}
+
+/**
+The default maximum depth of nested values in a document.
+*/
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            empty: EmptyPolicy::default(),
+            empty_fields: HashMap::new(),
+            keyword_fields: HashSet::new(),
+            field_hints: HashMap::new(),
+            case_insensitive_fields: HashSet::new(),
+            case_insensitive_keywords: false,
+            allowed_fields: None,
+            denied_fields: HashSet::new(),
+            separator: ".".to_owned(),
+            source: false,
+            compress_source: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+/**
+What to do with empty strings and null values.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmptyPolicy {
+    /**
+    Keep empty strings as fields.
+
+    Null values don't get a field in the schema, but still count
+    towards the shape of the document.
+    */
+    Keep,
+    /**
+    Drop empty strings and null values as if they were never there.
+    */
+    Skip,
+}
+
+impl Default for EmptyPolicy {
+    fn default() -> Self {
+        EmptyPolicy::Keep
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options::default()
+    }
+
+    /**
+    Set the policy for empty values in all fields.
+    */
+    pub fn empty(mut self, policy: EmptyPolicy) -> Self {
+        self.empty = policy;
+        self
+    }
+
+    /**
+    Set the policy for empty values in a specific field.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn empty_field(mut self, field: impl Into<String>, policy: EmptyPolicy) -> Self {
+        self.empty_fields.insert(field.into(), policy);
+        self
+    }
+
+    /**
+    Index the strings in a field as keywords.
+
+    Strings are normally split into words, so they can be searched for by
+    any word in them. Keywords are kept whole instead, so they're matched
+    exactly, or by their prefix. This suits values like hostnames or ids.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn keyword_field(mut self, field: impl Into<String>) -> Self {
+        self.keyword_fields.insert(field.into());
+        self
+    }
+
+    /**
+    Index the values of a field according to a hint instead of their type.
+
+    Hints override keyword and case-insensitive fields for strings, but a
+    `Raw` field can still be case-insensitive. A hinted field gets a different
+    type from an unhinted one, so it's kept in a different index.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn field_hint(mut self, field: impl Into<String>, hint: FieldHint) -> Self {
+        self.field_hints.insert(field.into(), hint);
+        self
+    }
+
+    /**
+    Index the values of each field in the map according to its hint.
+    */
+    pub fn field_hints(mut self, hints: FieldHints) -> Self {
+        self.field_hints.extend(hints);
+        self
+    }
+
+    /**
+    Index the strings in a field as keywords that ignore case.
+
+    Like other keywords, values are kept whole, but they're lowercased when
+    indexed and queried, so `Info` is matched by `info`. The original value
+    is still returned from searches.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn case_insensitive_field(mut self, field: impl Into<String>) -> Self {
+        self.case_insensitive_fields.insert(field.into());
+        self
+    }
+
+    /**
+    Index all keyword fields so they ignore case.
+    */
+    pub fn case_insensitive_keywords(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive_keywords = case_insensitive;
+        self
+    }
+
+    /**
+    Set the separator between components of flattened field names.
+    */
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /**
+    Capture the original document as JSON in a reserved `_source` field.
+
+    Flattening is lossy, so the document rebuilt from its fields might not
+    match what was indexed. The source is stored verbatim but not indexed,
+    so it can be returned as-is but not searched.
+    */
+    pub fn source(mut self, capture: bool) -> Self {
+        self.source = capture;
+        self
+    }
+
+    /**
+    Compress the captured `_source` field.
+
+    The source is compressed with raw DEFLATE (RFC 1951) and stored in a
+    bytes field instead of a text field. `Searcher::search_source` decompresses
+    it transparently, and `decompress_source` can be used on the base64 bytes
+    returned by other searches. This has no effect unless `source` is enabled.
+    */
+    pub fn compress_source(mut self, compress: bool) -> Self {
+        self.compress_source = compress;
+        self
+    }
+
+    /**
+    Set the maximum depth of nested values in a document.
+
+    Each object, array, tuple, or enum variant is a level of nesting. Documents
+    nested deeper than this are rejected instead of being flattened, so deeply
+    nested or recursive values can't grow field names without bound.
+    */
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /**
+    Only index the given field, along with any other allowed fields.
+
+    Once a field is allowed, fields that aren't are dropped from the document
+    and its schema. Allowing a field also allows the fields nested in it, so
+    allowing `a` allows `a.b`.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn allow_field(mut self, field: impl Into<String>) -> Self {
+        self.allowed_fields.get_or_insert_with(HashSet::new).insert(field.into());
+        self
+    }
+
+    /**
+    Never index the given field.
+
+    The field is dropped from the document and its schema, so it can't be
+    searched and doesn't count towards the shape of the document. Denying a
+    field also denies the fields nested in it, and takes precedence over
+    allowing it.
+
+    The field is the full flattened name, like `a.b`.
+    */
+    pub fn deny_field(mut self, field: impl Into<String>) -> Self {
+        self.denied_fields.insert(field.into());
+        self
+    }
+
+    fn empty_policy(&self, field: &str) -> EmptyPolicy {
+        self.empty_fields.get(field).cloned().unwrap_or(self.empty)
+    }
+
+    fn is_case_insensitive(&self, field: &str) -> bool {
+        self.case_insensitive_fields.contains(field) || (self.case_insensitive_keywords && self.keyword_fields.contains(field))
+    }
+
+    fn has_field_filter(&self) -> bool {
+        self.allowed_fields.is_some() || !self.denied_fields.is_empty()
+    }
+
+    /**
+    Whether a field, or one of the fields it's nested in, is in the set.
+    */
+    fn contains_field_or_parent(&self, fields: &HashSet<String>, field: &str) -> bool {
+        if self.separator.is_empty() {
+            return fields.contains(field);
+        }
+
+        let mut end = field.len();
+
+        loop {
+            if fields.contains(&field[..end]) {
+                return true;
+            }
+
+            match field[..end].rfind(&*self.separator) {
+                Some(i) => end = i,
+                None => return false,
+            }
+        }
+    }
+
+    /**
+    Whether a field passes the allowlist and denylist.
+    */
+    fn includes_field(&self, field: &str) -> bool {
+        if self.contains_field_or_parent(&self.denied_fields, field) {
+            return false;
+        }
+
+        match self.allowed_fields {
+            Some(ref allowed) => self.contains_field_or_parent(allowed, field),
+            None => true,
+        }
+    }
+
+    /**
+    Whether a field might have allowed fields nested in it.
+    */
+    fn may_include_children(&self, field: &str) -> bool {
+        let prefix = format!("{}{}", field, self.separator);
+
+        match self.allowed_fields {
+            Some(ref allowed) => allowed.iter().any(|allowed| allowed.starts_with(&prefix)),
+            None => false,
+        }
+    }
+
+    /**
+    Drop the fields that aren't included from a JSON value.
+    */
+    fn filter_source(&self, path: &str, value: serde_json::Value) -> Option<serde_json::Value> {
+        let included = path.is_empty() || self.includes_field(path);
+
+        match value {
+            serde_json::Value::Object(fields) => {
+                if !included && !self.may_include_children(path) {
+                    return None;
+                }
+
+                let fields: serde_json::Map<String, serde_json::Value> = fields
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        let path = if path.is_empty() {
+                            name.clone()
+                        } else {
+                            format!("{}{}{}", path, self.separator, name)
+                        };
+
+                        self.filter_source(&path, value).map(|value| (name, value))
+                    })
+                    .collect();
+
+                Some(serde_json::Value::Object(fields))
+            },
+            // Elements of arrays are values for the array's field
+            serde_json::Value::Array(values) => Some(serde_json::Value::Array(
+                values.into_iter().filter_map(|value| self.filter_source(path, value)).collect(),
+            )),
+            value if included => Some(value),
+            _ => None,
+        }
+    }
+}
+
 impl Doc {
     pub fn build(doc: impl Serialize) -> Result<Self, crate::Error> {
-        let mut ser = FieldCollector::new();
+        Doc::build_with(doc, &Options::default())
+    }
+
+    pub fn build_with(doc: impl Serialize, options: &Options) -> Result<Self, crate::Error> {
+        let mut ser = FieldCollector::new(options);
         doc.serialize(&mut ser)?;
 
-        let hash = {
-            let mut hasher = hash_map::DefaultHasher::new();
+        if options.source {
+            // Fields that aren't indexed shouldn't leak through the source either
+            let source = if options.has_field_filter() {
+                let source = options.filter_source("", serde_json::to_value(&doc)?).unwrap_or(serde_json::Value::Null);
 
-            for (k, v) in &ser.fields {
-                (k, v.ty()).hash(&mut hasher);
-            }
+                serde_json::to_string(&source)?
+            } else {
+                serde_json::to_string(&doc)?
+            };
 
-            hasher.finish()
-        };
+            let source = if options.compress_source {
+                Value::CompressedSource(compress_source(&source)?)
+            } else {
+                Value::Source(source)
+            };
+
+            ser.fields.push((SOURCE_FIELD.to_owned(), source));
+        }
+
+        // Anonymous fields like `_0` are generated for tuple elements
+        // If one of them lands on the same name as a user-provided field then
+        // the two would be silently merged, so we reject the document instead
+        if let Some(field) = ser.generated.intersection(&ser.named).next() {
+            return Err(Invalid::custom(format_args!("the generated field `{}` collides with a named field", field)).into());
+        }
 
-        Ok(Doc {
-            index: hash,
+        let mut doc = Doc {
+            index: 0,
+            separator: options.separator.clone(),
             fields: ser.fields,
-        })
+        };
+
+        doc.index = doc.index_of(|_| true);
+
+        Ok(doc)
     }
 
     pub fn index(&self) -> IndexId {
         self.index
     }
 
-    pub fn indexable(&self) -> IndexableDoc {
-        let schema = {
-            let mut schema = SchemaBuilder::new();
-            let mut seen = HashMap::new();
-
-            for (k, v) in &self.fields {
-                match seen.entry(k) {
-                    hash_map::Entry::Occupied(entry) => {
-                        // Ensure any duplicate entries have the same type
-                        assert!(*entry.get() == v.ty());
-                    },
-                    hash_map::Entry::Vacant(entry) => {
-                        // We only need to build each field once
-                        entry.insert(v.ty());
-
-                        match v {
-                            Value::Signed(_) | Value::Unsigned(_) | Value::Float(_) => {
-                                schema.add_i64_field(k, FAST);
-                            },
-                            Value::Bytes(_) => {
-                                schema.add_bytes_field(k);
-                            },
-                            Value::Bool(_) => {
-                                schema.add_text_field(k, STRING | STORED);
-                            },
-                            Value::Str(_) => {
-                                schema.add_text_field(k, TEXT | STORED);
-                            },
-                            Value::None => (),
-                        }
+    /**
+    Get the id of the index for just the fields of this document that match a predicate.
+    */
+    pub(crate) fn index_of(&self, include: impl Fn(&str) -> bool) -> IndexId {
+        let mut hasher = hash_map::DefaultHasher::new();
+
+        // The same record flattened with different separators has different fields
+        self.separator.hash(&mut hasher);
+
+        // Fields are hashed as a sorted set, so the order they were serialized in
+        // doesn't matter. Elements of arrays are multiple values for the same field,
+        // so each field is only hashed once regardless of how many values it has
+        let fields: BTreeSet<(&str, &str)> = self.fields
+            .iter()
+            .filter(|(k, _)| include(k))
+            .map(|(k, v)| (&**k, v.ty()))
+            .collect();
+        fields.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /**
+    Get the names of the fields in this document that are indexed.
+    */
+    pub(crate) fn field_names(&self) -> HashSet<&str> {
+        self.fields
+            .iter()
+            .filter(|(_, v)| match v {
+                Value::None => false,
+                _ => true,
+            })
+            .map(|(k, _)| &**k)
+            .collect()
+    }
+
+    /**
+    Check that the document can be indexed.
+
+    This runs the same checks as `indexable`, without building a schema or
+    document, and reports every problem it finds instead of just the first.
+    */
+    pub fn validate(&self) -> Result<(), Vec<crate::Error>> {
+        let errors = self.conflicts();
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+    Find the fields that appear more than once with different types.
+
+    Each field is only reported once, against the first type it was seen with.
+    */
+    fn conflicts(&self) -> Vec<crate::Error> {
+        let mut errors = Vec::new();
+        let mut seen = HashMap::new();
+        let mut reported = HashSet::new();
+
+        for (k, v) in &self.fields {
+            match seen.entry(k) {
+                hash_map::Entry::Occupied(entry) => {
+                    if *entry.get() != v.ty() && reported.insert(k) {
+                        errors.push(crate::Error::InvalidDoc(format!("the field `{}` has conflicting types `{}` and `{}`", k, entry.get(), v.ty())));
                     }
+                },
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(v.ty());
                 }
             }
+        }
 
-            schema.build()
-        };
+        errors
+    }
+
+    /**
+    Build a tantivy schema and document for this document.
+
+    Fields that appear more than once with different types can't be
+    indexed, so they're returned as an error.
+    */
+    pub fn indexable(&self) -> Result<IndexableDoc, crate::Error> {
+        if let Some(err) = self.conflicts().into_iter().next() {
+            return Err(err);
+        }
 
-        let doc = {
-            let mut doc = Document::new();
-
-            for (k, v) in &self.fields {
-                match v {
-                    Value::Signed(v) => {
-                        doc.add_i64(schema.get_field(k).expect("missing field"), *v);
-                    },
-                    Value::Unsigned(v) => {
-                        doc.add_u64(schema.get_field(k).expect("missing field"), *v);
-                    },
-                    Value::Float(v) => {
-                        doc.add_u64(schema.get_field(k).expect("missing field"), v.to_bits());
+        let schema = {
+            let mut schema = SchemaBuilder::new();
+            let mut seen = HashSet::new();
+
+            // Fields are added in order of their names, so documents with the same
+            // fields in a different order build the same schema for the same index
+            let mut fields: Vec<_> = self.fields.iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+            for (k, v) in fields {
+                // Conflicting types were already checked, so we only need to build each field once
+                if seen.insert(k) {
+                    // Integers and dates are `INDEXED | FAST | STORED`:
+                    // - `INDEXED` so term and range queries from the query parser match them
+                    // - `FAST` so they can be sorted on and read without loading documents
+                    // - `STORED` so they're returned in search results
+                    // Floats are `INDEXED | FAST` only, because their stored value would be encoded.
+                    // Their fast field is multi-valued so every element of an array is kept
+                    // Booleans are `INDEXED | STORED` as `0` or `1`, without `FAST` to tell them apart from integers
+                    match v {
+                        Value::Signed(_) => {
+                            schema.add_i64_field(k, INDEXED | FAST | STORED);
+                        },
+                        Value::Unsigned(_) => {
+                            schema.add_u64_field(k, INDEXED | FAST | STORED);
+                        },
+                        Value::Date(_) => {
+                            schema.add_i64_field(k, INDEXED | FAST | STORED);
+                        },
+                        Value::Float(_) => {
+                            schema.add_u64_field(k, IntOptions::default().set_indexed().set_fast(Cardinality::MultiValues));
+                        },
+                        Value::Bytes(_) => {
+                            schema.add_bytes_field(k);
+                        },
+                        Value::Bool(_) => {
+                            schema.add_u64_field(k, INDEXED | STORED);
+                        },
+                        Value::Str(_) | Value::Json(_) => {
+                            schema.add_text_field(k, TEXT | STORED);
+                        },
+                        Value::Keyword(_) => {
+                            let indexing = TextFieldIndexing::default()
+                                .set_tokenizer(KEYWORD_TOKENIZER)
+                                .set_index_option(IndexRecordOption::Basic);
+
+                            schema.add_text_field(k, TextOptions::default().set_indexing_options(indexing).set_stored());
+                        },
+                        Value::Lowercase(_) => {
+                            let indexing = TextFieldIndexing::default()
+                                .set_tokenizer(LOWERCASE_KEYWORD_TOKENIZER)
+                                .set_index_option(IndexRecordOption::Basic);
+
+                            schema.add_text_field(k, TextOptions::default().set_indexing_options(indexing).set_stored());
+                        },
+                        Value::Source(_) => {
+                            schema.add_text_field(k, TextOptions::default().set_stored());
+                        },
+                        Value::CompressedSource(_) => {
+                            schema.add_bytes_field(k);
+                        },
+                        Value::Path(_) => {
+                            let indexing = TextFieldIndexing::default()
+                                .set_tokenizer(PATH_TOKENIZER)
+                                .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+
+                            schema.add_text_field(k, TextOptions::default().set_indexing_options(indexing).set_stored());
+                        },
+                        Value::None => (),
                     }
-                    Value::Bytes(v) => {
-                        doc.add_bytes(schema.get_field(k).expect("missing field"), v.to_owned());
-                    },
-                    Value::Bool(v) => {
-                        let v = if *v { "true" } else { "false" };
-
-                        doc.add_text(schema.get_field(k).expect("missing field"), v);
-                    },
-                    Value::Str(v) => {
-                        doc.add_text(schema.get_field(k).expect("missing field"), v);
-                    },
-                    Value::None => (),
                 }
             }
 
-            doc
+            schema.build()
         };
 
-        IndexableDoc {
+        let doc = self.document(&schema);
+
+        Ok(IndexableDoc {
             index: self.index,
             schema,
             doc,
+        })
+    }
+
+    /**
+    Build a tantivy document for the given schema.
+
+    The schema must contain all of the fields in this document, but
+    it may contain others too.
+    */
+    pub fn document(&self, schema: &Schema) -> Document {
+        let mut doc = Document::new();
+
+        for (k, v) in &self.fields {
+            match v {
+                Value::Signed(v) | Value::Date(v) => {
+                    doc.add_i64(schema.get_field(k).expect("missing field"), *v);
+                },
+                Value::Unsigned(v) => {
+                    doc.add_u64(schema.get_field(k).expect("missing field"), *v);
+                },
+                Value::Float(v) => {
+                    doc.add_u64(schema.get_field(k).expect("missing field"), f64_to_u64(*v));
+                }
+                Value::Bytes(v) | Value::CompressedSource(v) => {
+                    doc.add_bytes(schema.get_field(k).expect("missing field"), v.to_owned());
+                },
+                Value::Bool(v) => {
+                    doc.add_u64(schema.get_field(k).expect("missing field"), *v as u64);
+                },
+                Value::Str(v) | Value::Json(v) | Value::Path(v) | Value::Keyword(v) | Value::Lowercase(v) | Value::Source(v) => {
+                    doc.add_text(schema.get_field(k).expect("missing field"), v);
+                },
+                Value::None => (),
+            }
         }
+
+        doc
+    }
+
+    /**
+    Convert the document back into JSON.
+
+    Flattened field names are split on the separator to nest them back into
+    objects. A field with multiple values becomes an array, and a field with
+    a single value is unwrapped, so arrays with one element aren't arrays anymore.
+
+    Floats keep their exact value, and stay floats even when they're whole numbers.
+    JSON can't represent `NaN` or infinities, so they become `null`.
+    */
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let mut root = serde_json::Map::new();
+
+        for (name, value) in &self.fields {
+            let value = serde_json::to_value(value).expect("failed to serialize value");
+
+            let mut path: Vec<&str> = name.split(&*self.separator).collect();
+            let last = path.pop().expect("empty field name");
+
+            let mut target = &mut root;
+            for component in path {
+                let next = target.entry(component.to_owned()).or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+
+                if !next.is_object() {
+                    *next = serde_json::Value::Object(serde_json::Map::new());
+                }
+
+                target = next.as_object_mut().expect("missing object");
+            }
+
+            // Repeated fields are the values of an array
+            match target.remove(last) {
+                Some(serde_json::Value::Array(mut values)) => {
+                    values.push(value);
+                    target.insert(last.to_owned(), serde_json::Value::Array(values));
+                },
+                Some(existing) => {
+                    target.insert(last.to_owned(), serde_json::Value::Array(vec![existing, value]));
+                },
+                None => {
+                    target.insert(last.to_owned(), value);
+                },
+            }
+        }
+
+        serde_json::Value::Object(root)
     }
 }
 
 /**
 An implementation of `serde::Serializer` that collects and flattens fields.
 */
-struct FieldCollector {
+struct FieldCollector<'o> {
+    options: &'o Options,
     path: FieldPath,
     current_field: Option<String>,
     fields: Vec<(String, Value)>,
+    generated: HashSet<String>,
+    named: HashSet<String>,
+    time: Option<TimeStruct>,
+}
+
+/**
+A `SystemTime` or `Duration` that's being serialized.
+
+Serde serializes them as structs with a field for seconds and a field for
+nanoseconds. Instead of flattening those, the fields are captured so the
+whole struct can be indexed as a single value.
+*/
+struct TimeStruct {
+    kind: TimeKind,
+    secs: Option<u64>,
+    nanos: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+enum TimeKind {
+    SystemTime,
+    Duration,
+}
+
+impl TimeKind {
+    fn from_struct(name: &str, len: usize) -> Option<Self> {
+        match (name, len) {
+            ("SystemTime", 2) => Some(TimeKind::SystemTime),
+            ("Duration", 2) => Some(TimeKind::Duration),
+            _ => None,
+        }
+    }
+
+    fn fields(&self) -> (&'static str, &'static str) {
+        match *self {
+            TimeKind::SystemTime => ("secs_since_epoch", "nanos_since_epoch"),
+            TimeKind::Duration => ("secs", "nanos"),
+        }
+    }
+}
+
+impl TimeStruct {
+    /**
+    Capture a field of the struct.
+
+    If the field isn't one we expect then the struct isn't a time after all.
+    */
+    fn capture(&mut self, key: &str, value: Option<u64>) -> bool {
+        let (secs, nanos) = self.kind.fields();
+
+        match value {
+            Some(value) if key == secs => self.secs = Some(value),
+            Some(value) if key == nanos => self.nanos = Some(value),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /**
+    Get the value of the time as milliseconds.
+
+    Times are indexed as dates, and durations are indexed as a number of milliseconds.
+    */
+    fn value(&self) -> Option<Value> {
+        let millis = self.secs?.saturating_mul(1_000).saturating_add(self.nanos? / 1_000_000);
+
+        match self.kind {
+            TimeKind::SystemTime => Some(Value::Date(millis.min(i64::max_value() as u64) as i64)),
+            TimeKind::Duration => Some(Value::Unsigned(millis)),
+        }
+    }
+
+    /**
+    The fields that were captured, in the order they're serialized.
+    */
+    fn captured(&self) -> Vec<(&'static str, u64)> {
+        let (secs, nanos) = self.kind.fields();
+
+        let mut captured = Vec::new();
+        if let Some(v) = self.secs {
+            captured.push((secs, v));
+        }
+        if let Some(v) = self.nanos {
+            captured.push((nanos, v));
+        }
+
+        captured
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -159,27 +1111,169 @@ pub enum Value {
     Signed(i64),
     Unsigned(u64),
     Float(f64),
+    Date(i64),
     Bytes(Vec<u8>),
     Str(String),
+    Json(String),
+    Path(String),
+    Keyword(String),
+    Lowercase(String),
+    Source(String),
+    CompressedSource(Vec<u8>),
     Bool(bool),
     None,
 }
 
+/**
+Values are serialized as the JSON scalar they came from.
+
+Bytes are serialized as base64 strings, the same way they're returned from searches.
+*/
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            Value::Signed(v) | Value::Date(v) => serializer.serialize_i64(v),
+            Value::Unsigned(v) => serializer.serialize_u64(v),
+            Value::Float(v) => serializer.serialize_f64(v),
+            Value::Bytes(ref v) | Value::CompressedSource(ref v) => serializer.serialize_str(&base64::encode(v)),
+            Value::Str(ref v) | Value::Json(ref v) | Value::Path(ref v) | Value::Keyword(ref v) | Value::Lowercase(ref v) | Value::Source(ref v) => serializer.serialize_str(v),
+            Value::Bool(v) => serializer.serialize_bool(v),
+            Value::None => serializer.serialize_unit(),
+        }
+    }
+}
+
 impl Value {
     fn ty(&self) -> &'static str {
         match *self {
             Value::Signed(_) => "signed",
             Value::Unsigned(_) => "unsigned",
             Value::Float(_) => "float",
+            Value::Date(_) => "date",
             Value::Bytes(_) => "bytes",
             Value::Str(_) => "string",
+            Value::Json(_) => "json",
+            Value::Path(_) => "path",
+            Value::Keyword(_) => "keyword",
+            Value::Lowercase(_) => "lowercase_keyword",
+            Value::Source(_) => "source",
+            Value::CompressedSource(_) => "compressed_source",
             Value::Bool(_) => "bool",
             Value::None => "none",
         }
     }
 }
 
+const HIGHEST_BIT: u64 = 1 << 63;
+
+/**
+Encode a float as a `u64` that sorts in the same order.
+
+Tantivy doesn't have a float field, so floats are indexed in a `u64`
+field using this encoding. That keeps range queries over them meaningful.
+*/
+pub fn f64_to_u64(v: f64) -> u64 {
+    let bits = v.to_bits();
+
+    if bits & HIGHEST_BIT == 0 {
+        bits ^ HIGHEST_BIT
+    } else {
+        !bits
+    }
+}
+
+/**
+Decode a float that was encoded with `f64_to_u64`.
+*/
+pub fn u64_to_f64(v: u64) -> f64 {
+    if v & HIGHEST_BIT != 0 {
+        f64::from_bits(v ^ HIGHEST_BIT)
+    } else {
+        f64::from_bits(!v)
+    }
+}
+
+/**
+Whether a field holds encoded floats.
+
+Float fields are the only `u64` fields that aren't stored, because the
+stored value would be the encoded one.
+*/
+pub fn is_float_field(entry: &FieldEntry) -> bool {
+    match *entry.field_type() {
+        FieldType::U64(ref options) => !options.is_stored(),
+        _ => false,
+    }
+}
+
+/**
+A reader for the encoded values of a float field in a segment.
+
+Floats are kept in a multi-valued fast field, so every element of an array
+is kept. Indexes created before that keep a single value for each document.
+*/
+pub enum FloatValues {
+    Single(FastFieldReader<u64>),
+    Multi(MultiValueIntFastFieldReader<u64>),
+}
+
+impl FloatValues {
+    pub fn open(reader: &SegmentReader, field: Field) -> Result<Self, TantivyError> {
+        let multi = match *reader.schema().get_field_entry(field).field_type() {
+            FieldType::U64(ref options) => options.get_fastfield_cardinality() == Some(Cardinality::MultiValues),
+            _ => false,
+        };
+
+        if multi {
+            Ok(FloatValues::Multi(reader.multi_fast_field_reader(field)?))
+        } else {
+            Ok(FloatValues::Single(reader.fast_field_reader(field)?))
+        }
+    }
+
+    /**
+    Get the encoded values of a document, in the order they were indexed.
+    */
+    pub fn get(&self, doc: DocId) -> Vec<u64> {
+        match *self {
+            FloatValues::Single(ref values) => vec![values.get(doc)],
+            FloatValues::Multi(ref values) => {
+                let mut vals = Vec::new();
+                values.get_vals(doc, &mut vals);
+
+                vals
+            },
+        }
+    }
+}
+
+/**
+Whether a field holds booleans.
+
+Booleans are indexed as `0` or `1` in a `u64` field. They're the only
+`u64` fields that are stored but aren't fast fields.
+
+Indexes created before booleans were numeric hold them as untokenized
+`true` or `false` text instead. They're the only text fields that use
+tantivy's `raw` tokenizer, so they're still read back as booleans, but
+they can only be queried as `true` or `false`, not `1` or `0`.
+*/
+pub fn is_bool_field(entry: &FieldEntry) -> bool {
+    match *entry.field_type() {
+        FieldType::U64(ref options) => options.is_stored() && !options.is_fast(),
+        FieldType::Str(ref options) => options
+            .get_indexing_options()
+            .map(|indexing| indexing.tokenizer() == "raw")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
 struct FieldPath {
+    separator: String,
     anonymous: u32,
     components: VecDeque<FieldComponent>,
 }
@@ -215,10 +1309,11 @@ impl FieldPath {
         self.components
             .iter()
             .map(|s| &s.value as &str)
+            .filter(|s| !s.is_empty())
             .chain(field)
             .fold(String::new(), |mut s, p| {
-                if s.len() > 0 {
-                    s.push('.');
+                if !s.is_empty() {
+                    s.push_str(&self.separator);
                 }
 
                 s.push_str(p);
@@ -244,58 +1339,167 @@ impl FieldPath {
     }
 }
 
-impl FieldCollector {
-    fn new() -> Self {
+impl<'o> FieldCollector<'o> {
+    fn new(options: &'o Options) -> Self {
         FieldCollector {
+            options,
             path: FieldPath {
+                separator: options.separator.clone(),
                 anonymous: 0,
                 components: VecDeque::new(),
             },
             current_field: None,
             fields: Vec::new(),
+            generated: HashSet::new(),
+            named: HashSet::new(),
+            time: None,
         }
     }
 
+    /**
+    Flatten the fields of a struct that looked like a time but wasn't.
+
+    The struct's path wasn't pushed when it started, so it's pushed here
+    and popped when the struct ends like any other.
+    */
+    fn flatten_time(&mut self, time: TimeStruct) -> Result<(), Invalid> {
+        self.push_path()?;
+
+        for (field, value) in time.captured() {
+            self.set_current_field(field.to_owned());
+            self.move_next_field(Value::Unsigned(value))?;
+        }
+
+        Ok(())
+    }
+
     fn set_current_field(&mut self, field: String) {
         assert!(self.current_field.is_none());
 
         self.current_field = Some(field);
     }
 
-    fn push_path(&mut self) {
-        if let Some(field) = self.current_field.take() {
-            self.path.push(true, field);
-        }
+    /**
+    Push the current field onto the path.
+
+    A component is always pushed, even if there's no current field, so
+    every push is balanced by a pop. Components without a field, like the
+    elements of an array, are skipped when building field names.
+    */
+    fn push_path(&mut self) -> Result<(), Invalid> {
+        self.check_depth()?;
+
+        let field = self.current_field.take().unwrap_or_default();
+        self.path.push(true, field);
+
+        Ok(())
     }
 
-    fn push_path_no_child_fields(&mut self) {
-        if let Some(field) = self.current_field.take() {
-            self.path.push(false, field);
+    fn push_path_no_child_fields(&mut self) -> Result<(), Invalid> {
+        self.check_depth()?;
+
+        let field = self.current_field.take().unwrap_or_default();
+        self.path.push(false, field);
+
+        Ok(())
+    }
+
+    /**
+    Ensure another component can be pushed onto the path.
+    */
+    fn check_depth(&self) -> Result<(), Invalid> {
+        if self.path.components.len() >= self.options.max_depth {
+            return Err(Invalid::custom(format_args!("the document is nested deeper than the maximum depth of {}", self.options.max_depth)));
         }
+
+        Ok(())
     }
 
     fn pop_path(&mut self) {
         self.path.pop();
     }
 
-    fn move_next_field(&mut self, value: Value) {
+    /**
+    Push the name of an enum variant onto the path.
+
+    The variant becomes part of the current field, so a variant `Http`
+    in a field `error` produces fields under `error.Http`.
+    */
+    fn push_variant_path(&mut self, allow_child_fields: bool, variant: &'static str) -> Result<(), Invalid> {
+        self.check_depth()?;
+
+        let field = match self.current_field.take() {
+            Some(field) => format!("{}{}{}", field, self.path.separator, variant),
+            None => variant.to_owned(),
+        };
+
+        self.path.push(allow_child_fields, field);
+
+        Ok(())
+    }
+
+    fn move_next_field(&mut self, value: Value) -> Result<(), Invalid> {
         let field = match self.path.components.back_mut() {
             Some(ref component) if !component.allow_child_fields => {
-                assert!(self.current_field.is_none());
-
-                self.path.current()
+                // Values in arrays are multiple values for the array's field
+                // Fields of objects in arrays are multiple values for the object's fields
+                let field = match self.current_field.take() {
+                    Some(field) => self.path.current_to(field),
+                    None => self.path.current(),
+                };
+                self.named.insert(field.clone());
+
+                field
             },
             _ => {
-                let field = self.current_field.take().unwrap_or_else(|| self.path.anonymous());
-                self.path.current_to(field)
+                if let Some(field) = self.current_field.take() {
+                    let field = self.path.current_to(field);
+                    self.named.insert(field.clone());
+
+                    field
+                } else {
+                    let field = self.path.anonymous();
+                    let field = self.path.current_to(field);
+                    self.generated.insert(field.clone());
+
+                    field
+                }
             }
         };
 
+        if !self.options.includes_field(&field) {
+            return Ok(());
+        }
+
+        let value = match self.options.field_hints.get(&field) {
+            Some(hint) => match hint.apply(&field, value)? {
+                Value::Keyword(v) if self.options.is_case_insensitive(&field) || self.options.case_insensitive_keywords => Value::Lowercase(v),
+                value => value,
+            },
+            None => match value {
+                Value::Str(v) if self.options.is_case_insensitive(&field) => Value::Lowercase(v),
+                Value::Str(v) if self.options.keyword_fields.contains(&field) => Value::Keyword(v),
+                value => value,
+            },
+        };
+
+        let empty = match value {
+            Value::None => true,
+            Value::Str(ref v) | Value::Keyword(ref v) | Value::Lowercase(ref v) => v.is_empty(),
+            _ => false,
+        };
+
+        if empty && self.options.empty_policy(&field) == EmptyPolicy::Skip {
+            return Ok(());
+        }
+
         self.fields.push((field, value));
+
+        Ok(())
     }
 }
 
-impl<'a> Serializer for &'a mut FieldCollector {
+impl<'a, 'o> Serializer for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -308,7 +1512,7 @@ impl<'a> Serializer for &'a mut FieldCollector {
     type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<(), Invalid> {
-        self.move_next_field(Value::Bool(v));
+        self.move_next_field(Value::Bool(v))?;
 
         Ok(())
     }
@@ -326,7 +1530,7 @@ impl<'a> Serializer for &'a mut FieldCollector {
     }
 
     fn serialize_i64(self, v: i64) -> Result<(), Invalid> {
-        self.move_next_field(Value::Signed(v));
+        self.move_next_field(Value::Signed(v))?;
 
         Ok(())
     }
@@ -344,7 +1548,7 @@ impl<'a> Serializer for &'a mut FieldCollector {
     }
 
     fn serialize_u64(self, v: u64) -> Result<(), Invalid> {
-        self.move_next_field(Value::Unsigned(v));
+        self.move_next_field(Value::Unsigned(v))?;
 
         Ok(())
     }
@@ -354,24 +1558,24 @@ impl<'a> Serializer for &'a mut FieldCollector {
     }
 
     fn serialize_f64(self, v: f64) -> Result<(), Invalid> {
-        self.move_next_field(Value::Float(v));
+        self.move_next_field(Value::Float(v))?;
         Ok(())
     }
 
     fn serialize_char(self, v: char) -> Result<(), Invalid> {
-        self.move_next_field(Value::Str(v.to_string()));
+        self.move_next_field(Value::Str(v.to_string()))?;
 
         Ok(())
     }
 
     fn serialize_str(self, v: &str) -> Result<(), Invalid> {
-        self.move_next_field(Value::Str(v.to_owned()));
+        self.move_next_field(Value::Str(v.to_owned()))?;
 
         Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<(), Invalid> {
-        self.move_next_field(Value::Bytes(v.to_owned()));
+        self.move_next_field(Value::Bytes(v.to_owned()))?;
 
         Ok(())
     }
@@ -388,7 +1592,7 @@ impl<'a> Serializer for &'a mut FieldCollector {
     }
 
     fn serialize_unit(self) -> Result<(), Invalid> {
-        self.move_next_field(Value::None);
+        self.move_next_field(Value::None)?;
         
         Ok(())
     }
@@ -406,14 +1610,38 @@ impl<'a> Serializer for &'a mut FieldCollector {
         self.serialize_str(variant)
     }
 
-    fn serialize_newtype_struct<T>(
-        self,
-        _name: &'static str,
-        value: &T,
-    ) -> Result<(), Invalid>
-    where
-        T: ?Sized + Serialize,
-    {
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Invalid>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == JSON_NEWTYPE {
+            let json = serde_json::to_string(value).map_err(Invalid::custom)?;
+            self.move_next_field(Value::Json(json))?;
+
+            return Ok(());
+        }
+
+        if name == PATH_NEWTYPE {
+            let path = value.serialize(KeyCollector)?;
+            self.move_next_field(Value::Path(path))?;
+
+            return Ok(());
+        }
+
+        if name == DATE_NEWTYPE {
+            let millis = serde_json::to_value(value)
+                .map_err(Invalid::custom)?
+                .as_i64()
+                .ok_or_else(|| Invalid::custom("dates must be millisecond timestamps"))?;
+            self.move_next_field(Value::Date(millis))?;
+
+            return Ok(());
+        }
+
         value.serialize(self)
     }
 
@@ -421,23 +1649,29 @@ impl<'a> Serializer for &'a mut FieldCollector {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
+        variant: &'static str,
+        value: &T,
     ) -> Result<(), Invalid>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!();
+        // The inner value is forwarded like a newtype struct,
+        // but is named by the variant
+        self.push_variant_path(false, variant)?;
+        value.serialize(&mut *self)?;
+        self.pop_path();
+
+        Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Invalid> {
-        self.push_path_no_child_fields();
+        self.push_path_no_child_fields()?;
 
         Ok(self)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Invalid> {
-        self.push_path();
+        self.push_path()?;
 
         Ok(self)
     }
@@ -447,7 +1681,7 @@ impl<'a> Serializer for &'a mut FieldCollector {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct, Invalid> {
-        self.push_path();
+        self.push_path()?;
 
         Ok(self)
     }
@@ -456,23 +1690,37 @@ impl<'a> Serializer for &'a mut FieldCollector {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Invalid> {
-        unimplemented!();
+        self.push_variant_path(true, variant)?;
+
+        Ok(self)
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Invalid> {
-        self.push_path();
+        self.push_path()?;
 
         Ok(self)
     }
 
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Invalid> {
+        if let Some(kind) = TimeKind::from_struct(name, len) {
+            self.check_depth()?;
+
+            self.time = Some(TimeStruct {
+                kind,
+                secs: None,
+                nanos: None,
+            });
+
+            return Ok(self);
+        }
+
         self.serialize_map(Some(len))
     }
 
@@ -480,14 +1728,16 @@ impl<'a> Serializer for &'a mut FieldCollector {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Invalid> {
-        unimplemented!()
+        self.push_variant_path(true, variant)?;
+
+        Ok(self)
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeSeq for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -505,7 +1755,7 @@ impl<'a> ser::SerializeSeq for &'a mut FieldCollector {
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeTuple for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -523,7 +1773,7 @@ impl<'a> ser::SerializeTuple for &'a mut FieldCollector {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeTupleStruct for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -541,23 +1791,25 @@ impl<'a> ser::SerializeTupleStruct for &'a mut FieldCollector {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeTupleVariant for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
-    fn serialize_field<T>(&mut self, _value: &T) -> Result<(), Invalid>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Invalid>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<(), Invalid> {
-        unimplemented!()
+        self.pop_path();
+
+        Ok(())
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeMap for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -565,9 +1817,9 @@ impl<'a> ser::SerializeMap for &'a mut FieldCollector {
     where
         T: ?Sized + Serialize,
     {
-        let key = key.serialize(KeyCollector).expect("invalid key");
+        let key = key.serialize(KeyCollector)?;
         self.set_current_field(key);
-        
+
         Ok(())
     }
 
@@ -587,7 +1839,7 @@ impl<'a> ser::SerializeMap for &'a mut FieldCollector {
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeStruct for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -595,6 +1847,18 @@ impl<'a> ser::SerializeStruct for &'a mut FieldCollector {
     where
         T: ?Sized + Serialize,
     {
+        if let Some(mut time) = self.time.take() {
+            let v = serde_json::to_value(value).ok().and_then(|v| v.as_u64());
+
+            if time.capture(key, v) {
+                self.time = Some(time);
+
+                return Ok(());
+            }
+
+            self.flatten_time(time)?;
+        }
+
         self.set_current_field(key.to_string());
         value.serialize(&mut **self)?;
 
@@ -602,12 +1866,22 @@ impl<'a> ser::SerializeStruct for &'a mut FieldCollector {
     }
 
     fn end(self) -> Result<(), Invalid> {
+        if let Some(time) = self.time.take() {
+            if let Some(value) = time.value() {
+                self.move_next_field(value)?;
+
+                return Ok(());
+            }
+
+            self.flatten_time(time)?;
+        }
+
         self.pop_path();
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut FieldCollector {
+impl<'a, 'o> ser::SerializeStructVariant for &'a mut FieldCollector<'o> {
     type Ok = ();
     type Error = Invalid;
 
@@ -720,9 +1994,9 @@ impl Serializer for KeyCollector {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<String, Invalid> {
-        Err(Invalid::custom("unsupported key type"))
+        Ok(variant.to_owned())
     }
 
     fn serialize_newtype_struct<T>(
@@ -798,8 +2072,11 @@ impl Serializer for KeyCollector {
     }
 }
 
+/**
+A value that couldn't be serialized into a document.
+*/
 #[derive(Debug)]
-struct Invalid(String);
+pub struct Invalid(String);
 
 impl Error for Invalid {
     fn custom<T>(msg: T) -> Self
@@ -903,4 +2180,533 @@ mod tests {
 
         assert_ne!(a.index(), b.index());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn json_fields_are_not_flattened() {
+        #[derive(Serialize)]
+        struct WithJson {
+            a: i32,
+            b: Json<Inner>,
+        }
+
+        let record = WithJson {
+            a: 1,
+            b: Json(Inner {
+                a: false,
+                b: ('a', 'b'),
+            }),
+        };
+
+        let expected = vec![
+            ("a".to_owned(), Value::Signed(1)),
+            ("b".to_owned(), Value::Json(r#"{"a":false,"b":["a","b"]}"#.into())),
+        ];
+
+        let doc = Doc::build(record).expect("failed to get document");
+
+        assert_eq!(expected, doc.fields);
+    }
+
+    #[test]
+    fn generated_fields_colliding_with_named_fields_are_rejected() {
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Element {
+            Pair(i32, i32),
+            Named(HashMap<&'static str, i32>),
+        }
+
+        #[derive(Serialize)]
+        struct Record {
+            t: Vec<Element>,
+        }
+
+        let mut named = HashMap::new();
+        named.insert("_0", 3);
+
+        // The tuple generates `t._0`, and the map names the same field
+        let colliding = Record {
+            t: vec![Element::Pair(1, 2), Element::Named(named)],
+        };
+
+        assert!(Doc::build(colliding).is_err());
+
+        let mut named = HashMap::new();
+        named.insert("a", 3);
+
+        let distinct = Record {
+            t: vec![Element::Pair(1, 2), Element::Named(named)],
+        };
+
+        assert!(Doc::build(distinct).is_ok());
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let valid = Doc::build(json!({
+            "a": 1,
+            "b": { "c": "text" }
+        })).expect("failed to get document");
+
+        assert!(valid.validate().is_ok());
+
+        let invalid = Doc::build(json!({
+            "a": [1, "b", true],
+            "c": [true, "d"],
+            "e": 1
+        })).expect("failed to get document");
+
+        let errors = invalid.validate().expect_err("expected an invalid document");
+
+        assert_eq!(2, errors.len());
+
+        // Indexing fails with the first of the same errors
+        let err = invalid.indexable().err().expect("expected an invalid document");
+        assert_eq!(errors[0].to_string(), err.to_string());
+    }
+
+    #[test]
+    fn unit_variant_keys_are_field_names() {
+        #[derive(Serialize, PartialEq, Eq, Hash)]
+        enum Status {
+            Ok,
+            Failed,
+        }
+
+        let mut record = HashMap::new();
+        record.insert(Status::Ok, 1);
+        record.insert(Status::Failed, 2);
+
+        let mut doc = Doc::build(record).expect("failed to get document");
+        doc.fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let expected = vec![
+            ("Failed".to_owned(), Value::Signed(2)),
+            ("Ok".to_owned(), Value::Signed(1)),
+        ];
+
+        assert_eq!(expected, doc.fields);
+    }
+
+    #[test]
+    fn unsupported_keys_are_errors() {
+        #[derive(Serialize, PartialEq, Eq, Hash)]
+        enum Key {
+            Id(i32),
+        }
+
+        let mut record = HashMap::new();
+        record.insert(Key::Id(1), 1);
+
+        assert!(Doc::build(record).is_err());
+    }
+
+    #[test]
+    fn empty_values_follow_policy() {
+        let record = json!({
+            "a": "",
+            "b": null,
+            "c": 1
+        });
+
+        let keep = Doc::build(&record).expect("failed to get document");
+        assert_eq!(vec![
+            ("a".to_owned(), Value::Str("".into())),
+            ("b".to_owned(), Value::None),
+            ("c".to_owned(), Value::Unsigned(1)),
+        ], keep.fields);
+
+        let skip = Doc::build_with(&record, &Options::new().empty(EmptyPolicy::Skip)).expect("failed to get document");
+        assert_eq!(vec![
+            ("c".to_owned(), Value::Unsigned(1)),
+        ], skip.fields);
+
+        let skip_a = Doc::build_with(&record, &Options::new().empty_field("a", EmptyPolicy::Skip)).expect("failed to get document");
+        assert_eq!(vec![
+            ("b".to_owned(), Value::None),
+            ("c".to_owned(), Value::Unsigned(1)),
+        ], skip_a.fields);
+    }
+
+    #[test]
+    fn floats_encode_in_order() {
+        let floats = [-100.5, -1.0, -0.25, 0.0, 0.25, 1.5, 100.5];
+
+        for pair in floats.windows(2) {
+            assert!(f64_to_u64(pair[0]) < f64_to_u64(pair[1]));
+        }
+
+        for f in &floats {
+            assert_eq!(*f, u64_to_f64(f64_to_u64(*f)));
+        }
+    }
+
+    #[test]
+    fn tuple_variants_are_flattened_under_the_variant() {
+        #[derive(Serialize)]
+        enum MyErr {
+            Http(u16, &'static str),
+        }
+
+        #[derive(Serialize)]
+        struct Record {
+            error: MyErr,
+        }
+
+        let doc = Doc::build(Record {
+            error: MyErr::Http(404, "not found"),
+        }).expect("failed to get document");
+
+        let expected = vec![
+            ("error.Http._0".to_owned(), Value::Unsigned(404)),
+            ("error.Http._1".to_owned(), Value::Str("not found".into())),
+        ];
+
+        assert_eq!(expected, doc.fields);
+    }
+
+    #[test]
+    fn struct_variants_are_flattened_under_the_variant() {
+        #[derive(Serialize)]
+        enum Event {
+            Login {
+                user: &'static str,
+                ip: &'static str,
+            },
+            Logout {
+                user: &'static str,
+            },
+        }
+
+        #[derive(Serialize)]
+        struct Record {
+            event: Event,
+        }
+
+        let login = Doc::build(Record {
+            event: Event::Login {
+                user: "me",
+                ip: "127.0.0.1",
+            },
+        }).expect("failed to get document");
+
+        let logout = Doc::build(Record {
+            event: Event::Logout {
+                user: "me",
+            },
+        }).expect("failed to get document");
+
+        assert_eq!(vec![
+            ("event.Login.user".to_owned(), Value::Str("me".into())),
+            ("event.Login.ip".to_owned(), Value::Str("127.0.0.1".into())),
+        ], login.fields);
+
+        assert_eq!(vec![
+            ("event.Logout.user".to_owned(), Value::Str("me".into())),
+        ], logout.fields);
+    }
+
+    #[test]
+    fn newtype_variants_are_named_by_the_variant() {
+        #[derive(Serialize)]
+        enum Id {
+            User(u64),
+        }
+
+        #[derive(Serialize)]
+        struct Record {
+            id: Id,
+        }
+
+        let doc = Doc::build(Record {
+            id: Id::User(42),
+        }).expect("failed to get document");
+
+        assert_eq!(vec![("id.User".to_owned(), Value::Unsigned(42))], doc.fields);
+    }
+
+    #[test]
+    fn arrays_of_different_lengths_have_same_index() {
+        let a = Doc::build(json!({ "d": [1, 2] })).expect("failed to get document");
+        let b = Doc::build(json!({ "d": [1, 2, 3] })).expect("failed to get document");
+
+        assert_eq!(a.index(), b.index());
+    }
+
+    #[test]
+    fn objects_in_arrays_are_multivalued_fields() {
+        let doc = Doc::build(json!({
+            "items": [
+                { "a": 1, "b": ["x", "y"] },
+                { "a": 2, "b": ["z"] }
+            ]
+        })).expect("failed to get document");
+
+        let expected = vec![
+            ("items.a".to_owned(), Value::Unsigned(1)),
+            ("items.b".to_owned(), Value::Str("x".into())),
+            ("items.b".to_owned(), Value::Str("y".into())),
+            ("items.a".to_owned(), Value::Unsigned(2)),
+            ("items.b".to_owned(), Value::Str("z".into())),
+        ];
+
+        assert_eq!(expected, doc.fields);
+    }
+
+    #[test]
+    fn conflicting_field_types_are_an_error() {
+        let doc = Doc::build(json!({
+            "x": [1, "str"]
+        })).expect("failed to get document");
+
+        match doc.indexable() {
+            Err(crate::Error::InvalidDoc(msg)) => assert!(msg.contains("`x`")),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn separator_is_used_for_field_names_and_index() {
+        let record = json!({
+            "a": {
+                "b": 1
+            }
+        });
+
+        let dotted = Doc::build_with(&record, &Options::new()).expect("failed to get document");
+        let underscored = Doc::build_with(&record, &Options::new().separator("__")).expect("failed to get document");
+
+        assert_eq!(vec![("a.b".to_owned(), Value::Unsigned(1))], dotted.fields);
+        assert_eq!(vec![("a__b".to_owned(), Value::Unsigned(1))], underscored.fields);
+
+        assert_ne!(dotted.index(), underscored.index());
+    }
+
+    #[test]
+    fn field_order_does_not_change_index() {
+        #[derive(Serialize)]
+        struct AB {
+            a: i32,
+            b: &'static str,
+        }
+
+        #[derive(Serialize)]
+        struct BA {
+            b: &'static str,
+            a: i32,
+        }
+
+        let ab = Doc::build(AB { a: 1, b: "b" }).expect("failed to get document");
+        let ba = Doc::build(BA { b: "b", a: 1 }).expect("failed to get document");
+
+        assert_eq!(ab.index(), ba.index());
+
+        // Documents for the same index must agree on the schema
+        let names = |doc: &Doc| -> Vec<String> {
+            doc.indexable()
+                .expect("failed to build doc")
+                .schema
+                .fields()
+                .iter()
+                .map(|field| field.name().to_owned())
+                .collect()
+        };
+
+        assert_eq!(names(&ab), names(&ba));
+    }
+
+    #[test]
+    fn docs_convert_back_to_json() {
+        let record = json!({
+            "a": {
+                "b": 1,
+                "c": ["x", "y"],
+                "d": {
+                    "e": -1.5
+                }
+            },
+            "f": true,
+            "g": null
+        });
+
+        let doc = Doc::build(&record).expect("failed to build doc");
+
+        assert_eq!(record, doc.to_json_value());
+    }
+
+    #[test]
+    fn values_serialize_as_json_scalars() {
+        let values = vec![
+            Value::Signed(-1),
+            Value::Unsigned(1),
+            Value::Float(1.5),
+            Value::Bytes(vec![1, 2, 3]),
+            Value::Str("a".to_owned()),
+            Value::Bool(true),
+            Value::None,
+        ];
+
+        assert_eq!(json!([-1, 1, 1.5, "AQID", "a", true, null]), serde_json::to_value(&values).expect("failed to serialize"));
+    }
+
+    #[test]
+    fn deeply_nested_docs_are_rejected() {
+        let mut record = json!("leaf");
+        for _ in 0..DEFAULT_MAX_DEPTH + 1 {
+            record = json!({ "a": record });
+        }
+
+        match Doc::build(&record) {
+            Err(crate::Error::Serialize(err)) => assert!(err.to_string().contains("maximum depth")),
+            _ => panic!("expected a serialization error"),
+        }
+
+        // The limit is exclusive of the leaf value
+        assert!(Doc::build_with(json!({ "a": { "b": 1 } }), &Options::new().max_depth(2)).is_ok());
+        assert!(Doc::build_with(json!({ "a": { "b": [1] } }), &Options::new().max_depth(2)).is_err());
+    }
+
+    #[test]
+    fn numeric_hints_index_every_value_as_the_same_type() {
+        let options = Options::new().field_hint("status", FieldHint::Numeric);
+
+        let ids = vec![json!({ "status": "404" }), json!({ "status": 404 }), json!({ "status": 404.0 }), json!({ "status": -1 })]
+            .into_iter()
+            .map(|record| Doc::build_with(&record, &options).expect("failed to build doc").index())
+            .collect::<HashSet<_>>();
+
+        assert_eq!(1, ids.len());
+
+        for record in vec![json!({ "status": "not found" }), json!({ "status": 4.5 }), json!({ "status": u64::max_value() })] {
+            match Doc::build_with(&record, &options) {
+                Err(crate::Error::Serialize(err)) => assert!(err.to_string().contains("`status`"), "{}", err),
+                _ => panic!("expected a serialization error for {}", record),
+            }
+        }
+    }
+
+    #[test]
+    fn filtered_fields_are_dropped() {
+        let record = json!({
+            "msg": "a record",
+            "secret": "hunter2",
+            "user": { "name": "alice", "token": "abc" },
+        });
+
+        let denied = Doc::build_with(&record, &Options::new().deny_field("secret").deny_field("user.token").source(true)).expect("failed to build doc");
+        let i = denied.indexable().expect("failed to build doc");
+
+        assert!(i.schema.get_field("secret").is_none());
+        assert!(i.schema.get_field("user.token").is_none());
+        assert!(i.schema.get_field("user.name").is_some());
+        assert_ne!(Doc::build(&record).expect("failed to build doc").index(), denied.index());
+
+        let source = denied.to_json_value()[SOURCE_FIELD].as_str().expect("missing source").to_owned();
+        assert!(!source.contains("hunter2"));
+        assert!(!source.contains("abc"));
+
+        let allowed = Doc::build_with(&record, &Options::new().allow_field("msg").allow_field("user.name")).expect("failed to build doc");
+
+        assert_eq!(json!({ "msg": "a record", "user": { "name": "alice" } }), allowed.to_json_value());
+    }
+
+    #[test]
+    fn floats_round_trip_exactly() {
+        let values = [
+            0.0,
+            -0.0,
+            0.1,
+            3.14,
+            -2.5,
+            std::f64::EPSILON,
+            std::f64::MIN_POSITIVE,
+            // The smallest subnormals
+            f64::from_bits(1),
+            -f64::from_bits(1),
+            std::f64::MAX,
+            std::f64::MIN,
+            std::f64::INFINITY,
+            std::f64::NEG_INFINITY,
+            // NaNs with payloads on either side of the infinities
+            f64::from_bits(0x7ff0_0000_0000_0001),
+            f64::from_bits(0xfff8_0000_0000_0001),
+            std::f64::NAN,
+        ];
+
+        for v in &values {
+            assert_eq!(v.to_bits(), u64_to_f64(f64_to_u64(*v)).to_bits(), "{:?}", v);
+        }
+
+        for v in values.iter().filter(|v| v.is_finite()) {
+            let doc = Doc::build(json!({ "f": v })).expect("failed to build doc");
+            let json = doc.to_json_value();
+
+            assert!(json["f"].is_f64(), "{:?}", v);
+            assert_eq!(v.to_bits(), json["f"].as_f64().expect("missing float").to_bits(), "{:?}", v);
+        }
+
+        let doc = Doc::build(json!({ "f": std::f64::NAN })).expect("failed to build doc");
+        assert_eq!(serde_json::Value::Null, doc.to_json_value()["f"]);
+    }
+
+    #[test]
+    fn rfc3339_dates_are_parsed_as_millis() {
+        assert_eq!(Some(0), parse_rfc3339("1970-01-01T00:00:00Z"));
+        assert_eq!(Some(1_577_836_800_000), parse_rfc3339("2020-01-01T00:00:00Z"));
+        assert_eq!(Some(1_577_838_600_000), parse_rfc3339("2020-01-01T10:30:00+10:00"));
+        assert_eq!(Some(1_582_977_600_123), parse_rfc3339("2020-02-29T12:00:00.1234z"));
+        assert_eq!(Some(-1), parse_rfc3339("1969-12-31T23:59:59.999Z"));
+
+        assert_eq!(None, parse_rfc3339("2019-02-29T00:00:00Z"));
+        assert_eq!(None, parse_rfc3339("2020-01-01T00:00:00"));
+        assert_eq!(None, parse_rfc3339("2020-01-01"));
+        assert_eq!(None, parse_rfc3339("1577836800000"));
+    }
+
+    #[test]
+    fn times_and_durations_are_single_values() {
+        #[derive(Serialize)]
+        struct Event {
+            at: SystemTime,
+            elapsed: std::time::Duration,
+            other: Duration,
+        }
+
+        // A struct that happens to share a name with `Duration`
+        #[derive(Serialize)]
+        struct Duration {
+            secs: u64,
+            unit: &'static str,
+        }
+
+        let event = Event {
+            at: UNIX_EPOCH + std::time::Duration::from_millis(1_500),
+            elapsed: std::time::Duration::from_micros(2_500_250),
+            other: Duration {
+                secs: 1,
+                unit: "s",
+            },
+        };
+
+        let doc = Doc::build(&event).expect("failed to build doc");
+
+        assert_eq!(
+            vec![
+                ("at".to_owned(), Value::Date(1_500)),
+                ("elapsed".to_owned(), Value::Unsigned(2_500)),
+                ("other.secs".to_owned(), Value::Unsigned(1)),
+                ("other.unit".to_owned(), Value::Str("s".to_owned())),
+            ],
+            doc.fields
+        );
+
+        let i = doc.indexable().expect("failed to build doc");
+        let at = i.schema.get_field("at").expect("missing field");
+
+        match *i.schema.get_field_entry(at).field_type() {
+            FieldType::I64(_) => (),
+            _ => panic!("expected a date field"),
+        }
+    }
+}
@@ -1,29 +1,116 @@
 use std::{
-    cmp::Ordering,
+    cmp::{
+        Ordering,
+        Reverse,
+    },
     collections::{
         BinaryHeap,
         HashMap,
+        HashSet,
+        VecDeque,
+    },
+    io::Write,
+    ops::{
+        Bound,
+        Deref,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering as AtomicOrdering,
+        },
+    },
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
     },
 };
 
 use tantivy::{
-    query::QueryParser,
-    collector::Collector,
+    Index,
+    schema::{
+        Field,
+        FieldEntry,
+        FieldType,
+        IndexRecordOption,
+        Schema,
+        Term,
+    },
+    query::{
+        AllQuery,
+        BooleanQuery,
+        FuzzyTermQuery,
+        Occur,
+        PhraseQuery,
+        Query,
+        QueryParser,
+        QueryParserError,
+        RangeQuery,
+        TermQuery,
+    },
+    collector::{
+        Collector,
+        CountCollector,
+    },
     Score,
     DocAddress,
+    SegmentId,
     SegmentLocalId,
     SegmentReader,
     DocId,
+    SnippetGenerator,
     TantivyError,
 };
 
-use failure;
+use log::Level;
+
+use serde::de::DeserializeOwned;
 
 use crate::{
     index::IndexId,
-    store::Store
+    logger::{
+        severity,
+        MSG_FIELD,
+        SEVERITY_FIELD,
+    },
+    error::Error,
+    schema::{
+        f64_to_u64,
+        is_bool_field,
+        FloatValues,
+        is_float_field,
+        parse_rfc3339,
+        to_millis,
+        SOURCE_FIELD,
+        decompress_source,
+        u64_to_f64,
+    },
+    store::{
+        Store,
+        TIMESTAMP_FIELD,
+    },
+    sync::lock,
+    tokenizer::is_lowercased,
 };
 
+/**
+The largest edit distance supported by fuzzy queries.
+*/
+const MAX_FUZZY_EDITS: u8 = 2;
+
+/**
+The number of documents to collect between checking whether a search has timed out.
+*/
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/**
+The number of candidates to collect for each result when deduplicating.
+*/
+const DEDUP_CANDIDATES: usize = 4;
+
 /**
 A searcher over the store.
 
@@ -31,163 +118,3440 @@ The searcher will look in all indexes.
 */
 pub struct Searcher {
     store: Store,
+    cache: Option<Arc<Mutex<DocCache>>>,
 }
 
 impl Searcher {
     pub fn new(store: Store) -> Self {
         Searcher {
             store,
+            cache: None,
+        }
+    }
+
+    /**
+    Create a searcher that caches recently returned documents.
+
+    Documents are cached by their index, segment, and id, so repeatedly fetching
+    the same hits doesn't need to re-read them from tantivy. Segments are never
+    changed once they're written, so cached documents stay valid across commits.
+    */
+    pub fn with_cache(store: Store, capacity: usize) -> Self {
+        Searcher {
+            store,
+            cache: Some(Arc::new(Mutex::new(DocCache::new(capacity)))),
         }
     }
 
+    /**
+    The number of documents that were returned from the cache.
+    */
+    pub fn cache_hits(&self) -> usize {
+        self.cache.as_ref().map(|cache| lock(&cache).hits).unwrap_or(0)
+    }
+
+    /**
+    Reload the searchers for every index so searches see everything that's been committed.
+
+    This is only needed when the store's reload policy is `ReloadPolicy::Manual`.
+    */
+    pub fn reload(&self) -> Result<(), crate::Error> {
+        self.store.reload()
+    }
+
+    /**
+    Search for documents matching a query string.
+
+    Hits are returned in descending order of score. A limit of `0` returns
+    every matching document, which is also true of the other searches.
+    */
     pub fn search(&self, query: &str, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
-        let mut lookup = HashMap::new();
-        let mut collector = MultiIndexCollector::with_limit(limit);
+        self.search_paged(query, 0, limit)
+    }
 
-        // We collect results from all indexes into a single collector
-        for (id, index) in self.store.indexes() {
-            let mut collector = CurrentIndexCollector::begin(id.to_owned(), &mut collector);
+    /**
+    Search for a page of documents, skipping the first `offset` hits.
 
-            index.load_searchers()?;
-            let searcher = index.searcher();
+    Each page has to collect and sort all of the hits before it, so
+    deep pages get more expensive.
+    */
+    pub fn search_paged(&self, query: &str, offset: usize, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes(), offset, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some))
+    }
 
-            let query_parser = QueryParser::for_index(&index, vec![]);
-            let query = query_parser.parse_query(query).map_err(|e| failure::err_msg(format!("{:?}", e)))?;
+    /**
+    Search for documents that score at least `min_score`.
 
-            searcher.search(&*query, &mut collector)?;
+    Documents below the threshold aren't collected at all, so they don't take
+    up space in the limit. That means fewer than `limit` documents may be
+    returned even if more than `limit` documents match the query.
+    */
+    pub fn search_with_min_score(&self, query: &str, min_score: Score, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let hits = self.hits_in_indexes_until(self.store.indexes(), 0, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some), Some(min_score), &Interrupt::never())?;
 
-            lookup.insert(id, (index, searcher));
-        }
+        Ok(hits.into_iter().map(|hit| hit.map(|hit| hit.json)))
+    }
 
-        Ok(collector.top_docs().into_iter().map(move |doc| {
-            let (ref index, ref searcher) = lookup[&doc.index];
+    /**
+    Search for documents, giving up if the search takes longer than `timeout`.
 
-            let doc = searcher.doc(doc.address)?;
-            Ok(index.schema().to_json(&doc))
-        }))
+    A search that times out returns a `Cancelled` error instead of partial results.
+    The timeout only covers finding matching documents, not reading them.
+    */
+    pub fn search_with_timeout(&self, query: &str, limit: usize, timeout: Duration) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let interrupt = Interrupt {
+            cancel: None,
+            deadline: Some(Instant::now() + timeout),
+        };
+
+        let hits = self.hits_in_indexes_until(self.store.indexes(), 0, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some), None, &interrupt)?;
+
+        Ok(hits.into_iter().map(|hit| hit.map(|hit| hit.json)))
     }
-}
 
-// NOTE: These types are pinched from tantivy directly
-// They've been tweaked to support an extra `IndexId` field
+    /**
+    Search for documents, giving up if `cancel` is set while searching.
 
-#[derive(Clone, Copy)]
-struct Doc {
-    score: Score,
-    index: IndexId,
-    address: DocAddress,
-}
+    The flag can be set from another thread. A search that's cancelled returns
+    a `Cancelled` error instead of partial results.
+    */
+    pub fn search_cancellable(&self, query: &str, limit: usize, cancel: &AtomicBool) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let interrupt = Interrupt {
+            cancel: Some(cancel),
+            deadline: None,
+        };
 
-impl PartialOrd for Doc {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+        let hits = self.hits_in_indexes_until(self.store.indexes(), 0, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some), None, &interrupt)?;
+
+        Ok(hits.into_iter().map(|hit| hit.map(|hit| hit.json)))
     }
-}
 
-impl Ord for Doc {
-    #[inline]
-    fn cmp(&self, other: &Self) -> Ordering {
-        other
-            .score
-            .partial_cmp(&self.score)
-            .unwrap_or_else(|| other.address.cmp(&self.address))
+    /**
+    Search for documents in a single index.
+
+    If the index has been widened then the index it was widened into is searched.
+    If the index doesn't exist then a `MissingIndex` error is returned.
+    */
+    pub fn search_in(&self, index: IndexId, query: &str, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let id = self.store.resolve(index);
+        let index = self.store.index(id).ok_or_else(|| Error::MissingIndex(id))?;
+
+        self.search_indexes(Some((id, index)), 0, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some))
     }
-}
 
-impl PartialEq for Doc {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(other) == Ordering::Equal
+    /**
+    Search for documents along with their scores and the index they came from.
+
+    Hits are returned in descending order of score.
+    */
+    pub fn search_hits(&self, query: &str, limit: usize) -> Result<impl IntoIterator<Item = Result<Hit, crate::Error>>, crate::Error> {
+        self.hits_in_indexes(self.store.indexes(), 0, limit, |index| parse_query(index, &self.store.config().default_fields, query).map(Some))
     }
-}
 
-impl Eq for Doc {}
+    /**
+    Search for documents along with a highlighted snippet of their message.
 
-struct MultiIndexCollector {
-    limit: usize,
-    heap: BinaryHeap<Doc>,
-    segment_id: u32,
-}
+    Matching terms in the snippet are wrapped in `<b>` tags. Documents from
+    indexes without a text `msg` field, or whose message doesn't match
+    the query, don't have a snippet.
+    */
+    pub fn search_highlighted(&self, query: &str, limit: usize) -> Result<Vec<Highlighted>, crate::Error> {
+        let hits = self.search_hits(query, limit)?;
 
-impl MultiIndexCollector {
-    fn with_limit(limit: usize) -> MultiIndexCollector {
-        if limit < 1 {
-            panic!("Limit must be strictly greater than 0.");
+        // The generator needs the terms in the query, so it's built per index
+        let mut generators = HashMap::new();
+
+        hits
+            .into_iter()
+            .map(|hit| {
+                let hit = hit?;
+
+                if !generators.contains_key(&hit.index) {
+                    let generator = self.snippet_generator(hit.index, query)?;
+                    generators.insert(hit.index, generator);
+                }
+
+                let snippet = match generators[&hit.index] {
+                    Some(ref generator) => {
+                        let json: serde_json::Value = serde_json::from_str(&hit.json)?;
+
+                        let msg = match json[MSG_FIELD] {
+                            serde_json::Value::Array(ref values) => values.iter().filter_map(|value| value.as_str()).collect::<Vec<_>>().join(" "),
+                            serde_json::Value::String(ref value) => value.clone(),
+                            _ => String::new(),
+                        };
+
+                        let snippet = generator.snippet(&msg).to_html();
+
+                        if snippet.is_empty() {
+                            None
+                        } else {
+                            Some(snippet)
+                        }
+                    },
+                    None => None,
+                };
+
+                Ok(Highlighted {
+                    hit,
+                    snippet,
+                })
+            })
+            .collect()
+    }
+
+    /**
+    Build a snippet generator for the message field of an index.
+
+    Indexes without a text `msg` field don't have a generator.
+    */
+    fn snippet_generator(&self, id: IndexId, query: &str) -> Result<Option<SnippetGenerator>, crate::Error> {
+        let index = match self.store.index(id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let schema = index.schema();
+        let field = match schema.get_field(MSG_FIELD) {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+
+        match *schema.get_field_entry(field).field_type() {
+            FieldType::Str(_) => {
+                let query = parse_query(&index, &self.store.config().default_fields, query)?;
+
+                Ok(Some(SnippetGenerator::new(&*index.searcher(), &*query, field)?))
+            },
+            _ => Ok(None),
         }
+    }
 
-        MultiIndexCollector {
-            limit,
-            heap: BinaryHeap::with_capacity(limit),
-            segment_id: 0,
+    /**
+    Search for documents and deserialize them into `T`.
+
+    Flattened fields are nested back into objects using the store's
+    separator, and fields with a single value are unwrapped from their
+    array. Fields that weren't indexed, like `None`s, are missing.
+    */
+    pub fn search_as<T>(&self, query: &str, limit: usize) -> Result<Vec<T>, crate::Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut docs = Vec::new();
+
+        for hit in self.search_hits(query, limit)? {
+            let hit = hit?;
+
+            let schema = self.schema_of(hit.index).ok_or_else(|| Error::MissingIndex(hit.index))?;
+            let doc = unflatten(&schema, &self.store.config().separator, serde_json::from_str(&hit.json)?);
+
+            docs.push(serde_json::from_value(doc)?);
         }
+
+        Ok(docs)
     }
 
-    fn top_docs(&self) -> impl IntoIterator<Item = Doc> {
-        let mut feature_docs: Vec<Doc> = self.heap.iter().cloned().collect();
-        feature_docs.sort();
-        feature_docs
+    /**
+    Search for documents matching a structured query.
+
+    Unlike query strings, values in the query don't need to be escaped.
+    */
+    pub fn search_query(&self, query: &QueryBuilder, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes(), 0, limit, |index| query.compile(index))
     }
 
-    #[inline]
-    fn at_capacity(&self) -> bool {
-        self.heap.len() >= self.limit
+    /**
+    Search for documents with a term in a field that's within `max_edits` of `term`.
+
+    Only text fields are searched. Edits are counted as Levenshtein distance,
+    so `lug` is within one edit of `log`.
+    */
+    pub fn search_fuzzy(&self, field: &str, term: &str, max_edits: u8, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_query(&QueryBuilder::fuzzy(field, term, max_edits), limit)
     }
 
-    fn set_segment_id(&mut self, segment_id: SegmentLocalId) {
-        self.segment_id = segment_id;
+    /**
+    Search for documents that have a value for a field.
+    */
+    pub fn has_field(&self, field: &str, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_query(&QueryBuilder::exists(field), limit)
     }
 
-    fn collect(&mut self, index: IndexId, doc: DocId, score: Score) {
-        if self.at_capacity() {
-            // It's ok to unwrap as long as a limit of 0 is forbidden.
-            let limit_doc: Doc = self
-                .heap
-                .peek()
-                .expect("Collector with size 0 is forbidden")
-                .clone();
-            if limit_doc.score < score {
-                let mut mut_head = self
-                    .heap
-                    .peek_mut()
-                    .expect("Collector with size 0 is forbidden");
-                mut_head.score = score;
-                mut_head.address = DocAddress(self.segment_id, doc);
+    /**
+    Count the documents that match a query across all indexes.
+
+    No documents are collected or read, so this is cheaper than searching
+    when only the number of matches is needed.
+    */
+    pub fn count(&self, query: &str) -> Result<usize, crate::Error> {
+        let start = Instant::now();
+        let mut count = 0;
+
+        for (id, index) in self.store.indexes() {
+            let query = parse_query(&index, &self.store.config().default_fields, query)?;
+            let mut collector = CountCollector::default();
+
+            self.store.load_searchers(id, &index)?;
+            index.searcher().search(&*query, &mut collector)?;
+
+            count += collector.count();
+        }
+
+        self.store.stats().record_search(start.elapsed());
+
+        Ok(count)
+    }
+
+    /**
+    Count the distinct values of a field in documents that match a query.
+
+    Counts are merged across all indexes, and the `top_n` most frequent values
+    are returned in descending order of their count. A `top_n` of `0` returns
+    every value. Numeric fields are read from
+    their fast fields, and text and boolean fields are read from stored documents.
+    Booleans are counted as `true` and `false`. Indexes without the field are skipped.
+    */
+    pub fn aggregate(&self, query: &str, field: &str, top_n: usize) -> Result<Vec<(String, u64)>, crate::Error> {
+        let start = Instant::now();
+        let mut counts = HashMap::new();
+
+        for (id, index) in self.store.indexes() {
+            let schema = index.schema();
+
+            let (field, ty) = match schema.get_field(field) {
+                Some(field) => {
+                    let entry = schema.get_field_entry(field);
+
+                    match *entry.field_type() {
+                        FieldType::U64(_) if is_float_field(entry) => (field, AggregateType::Float),
+                        FieldType::U64(_) if is_bool_field(entry) => (field, AggregateType::Stored),
+                        FieldType::U64(ref options) if options.is_fast() => (field, AggregateType::Unsigned),
+                        FieldType::I64(ref options) if options.is_fast() => (field, AggregateType::Signed),
+                        FieldType::Str(ref options) if options.is_stored() => (field, AggregateType::Stored),
+                        _ => continue,
+                    }
+                },
+                None => continue,
+            };
+
+            let query = parse_query(&index, &self.store.config().default_fields, query)?;
+            let mut collector = AggregateCollector::begin(field, ty, &mut counts);
+
+            self.store.load_searchers(id, &index)?;
+            let searcher = index.searcher();
+
+            searcher.search(&*query, &mut collector)?;
+
+            // Text and boolean fields aren't fast fields, so their values are read from stored documents
+            for address in collector.stored {
+                let doc = searcher.doc(address)?;
+
+                for value in doc.get_all(field) {
+                    let value = match *value {
+                        tantivy::schema::Value::Str(ref value) => value.clone(),
+                        tantivy::schema::Value::U64(value) => (value != 0).to_string(),
+                        _ => continue,
+                    };
+
+                    *collector.counts.entry(value).or_insert(0) += 1;
+                }
             }
-        } else {
-            let wrapped_doc = Doc {
-                score,
-                index,
-                address: DocAddress(self.segment_id, doc),
+        }
+
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        if top_n > 0 {
+            counts.truncate(top_n);
+        }
+
+        self.store.stats().record_search(start.elapsed());
+
+        Ok(counts)
+    }
+
+    /**
+    Search for documents, returning only the given fields from each hit.
+
+    Fields that aren't in the schema of the index a hit came from are omitted.
+    */
+    pub fn search_projected(&self, query: &str, limit: usize, fields: &[&str]) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let fields: HashSet<String> = fields.iter().map(|field| (*field).to_owned()).collect();
+        let hits = self.search(query, limit)?;
+
+        Ok(hits.into_iter().map(move |doc| {
+            let doc = doc?;
+
+            match serde_json::from_str(&doc)? {
+                serde_json::Value::Object(doc) => {
+                    let doc: serde_json::Map<String, serde_json::Value> = doc
+                        .into_iter()
+                        .filter(|(name, _)| fields.contains(name))
+                        .collect();
+
+                    Ok(serde_json::Value::Object(doc).to_string())
+                },
+                _ => Ok(doc),
+            }
+        }))
+    }
+
+    /**
+    Search for documents, returning the original source they were indexed from.
+
+    The source is only captured when documents are built with `Options::source`.
+    Documents without a source fall back to the JSON rebuilt from their fields.
+    Sources compressed with `Options::compress_source` are decompressed.
+    */
+    pub fn search_source(&self, query: &str, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let hits = self.search_hits(query, limit)?;
+        let store = self.store.clone();
+
+        Ok(hits.into_iter().map(move |hit| {
+            let hit = hit?;
+
+            let value: serde_json::Value = serde_json::from_str(&hit.json)?;
+            let source = match value[SOURCE_FIELD][0].as_str() {
+                Some(source) => source,
+                None => return Ok(hit.json),
             };
-            self.heap.push(wrapped_doc);
+
+            // Compressed sources are stored as bytes, which are rendered as base64
+            let compressed = store.index(hit.index).map(|index| {
+                let schema = index.schema();
+
+                match schema.get_field(SOURCE_FIELD).map(|field| schema.get_field_entry(field).field_type()) {
+                    Some(FieldType::Bytes) => true,
+                    _ => false,
+                }
+            }).unwrap_or(false);
+
+            if compressed {
+                let bytes = base64::decode(source).map_err(|err| Error::InvalidDoc(format!("the source isn't valid base64: {}", err)))?;
+
+                decompress_source(&bytes)
+            } else {
+                Ok(source.to_owned())
+            }
+        }))
+    }
+
+    /**
+    Search for documents, ordering them by the value of a field instead of by score.
+
+    The field needs to be a numeric fast field, like `timestamp` or `id`.
+    Documents in indexes where the field is missing or isn't a fast field
+    are treated as having the smallest value.
+    */
+    pub fn search_sorted(&self, query: &str, limit: usize, field: &str, ascending: bool) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let start = Instant::now();
+        let mut lookup = HashMap::new();
+        let mut collector = SortedCollector::with_limit(limit, ascending);
+
+        for (id, index) in self.store.indexes() {
+            let query = parse_query(&index, &self.store.config().default_fields, query)?;
+
+            let schema = index.schema();
+            let field = schema.get_field(field).and_then(|field| match *schema.get_field_entry(field).field_type() {
+                FieldType::U64(_) if is_float_field(schema.get_field_entry(field)) => Some((field, SortType::Float)),
+                FieldType::U64(ref options) if options.is_fast() => Some((field, SortType::Unsigned)),
+                FieldType::I64(ref options) if options.is_fast() => Some((field, SortType::Signed)),
+                _ => None,
+            });
+
+            let mut collector = CurrentSortedIndexCollector::begin(id, field, &mut collector);
+
+            self.store.load_searchers(id, &index)?;
+            let searcher = index.searcher();
+
+            searcher.search(&*query, &mut collector)?;
+
+            lookup.insert(id, (index, searcher));
         }
+
+        self.store.stats().record_search(start.elapsed());
+
+        let hits = self.hydrate(collector.top_docs(), lookup);
+
+        Ok(hits.into_iter().map(|hit| hit.map(|hit| hit.json)))
     }
-}
 
-struct CurrentIndexCollector<'a> {
-    index: IndexId,
-    collector: &'a mut MultiIndexCollector,
-}
+    /**
+    Search for records logged at `min_level` or above.
 
-impl<'a> CurrentIndexCollector<'a> {
-    fn begin(index: IndexId, collector: &'a mut MultiIndexCollector) -> Self {
-        CurrentIndexCollector {
-            index,
-            collector,
+    Indexes that don't have a severity field, because they weren't
+    written by the logger, are skipped.
+    */
+    pub fn search_with_levels(&self, query: &str, limit: usize, min_level: Level) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes(), 0, limit, |index| {
+            let field = match index.schema().get_field(SEVERITY_FIELD) {
+                Some(field) => field,
+                None => return Ok(None),
+            };
+
+            let query = parse_query(index, &self.store.config().default_fields, query)?;
+            let levels: Box<Query> = Box::new(RangeQuery::new_u64_bounds(field, Bound::Included(severity(min_level)), Bound::Unbounded));
+
+            Ok(Some(Box::new(BooleanQuery::from(vec![
+                (Occur::Must, query),
+                (Occur::Must, levels),
+            ])) as Box<Query>))
+        })
+    }
+
+    /**
+    Search for a text query within a structured filter.
+
+    Both the query and the filter must match. Indexes that don't have
+    all of the fields in the filter can't match it, so they're skipped.
+    */
+    pub fn search_filtered(&self, query: &str, filter: &QuerySpec, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes(), 0, limit, |index| {
+            let filter = match filter.compile(index)? {
+                Some(filter) => filter,
+                None => return Ok(None),
+            };
+
+            let query = parse_query(index, &self.store.config().default_fields, query)?;
+
+            Ok(Some(Box::new(BooleanQuery::from(vec![
+                (Occur::Must, query),
+                (Occur::Must, filter),
+            ])) as Box<Query>))
+        })
+    }
+
+    /**
+    Search for documents, keeping only the top hit for each distinct value of a field.
+
+    Extra candidates are collected so there are still enough results after
+    deduplicating, but if many hits share the same value then fewer than
+    `limit` documents may be returned. Documents without the field are
+    always treated as unique.
+    */
+    pub fn search_dedup(&self, query: &str, field: &str, limit: usize) -> Result<Vec<String>, crate::Error> {
+        let mut seen = HashSet::new();
+        let mut docs = Vec::new();
+
+        for doc in self.search(query, limit.saturating_mul(DEDUP_CANDIDATES))? {
+            let doc = doc?;
+
+            let value: serde_json::Value = serde_json::from_str(&doc)?;
+            if let Some(value) = value.get(field) {
+                if !seen.insert(value.to_string()) {
+                    continue;
+                }
+            }
+
+            docs.push(doc);
+
+            if limit > 0 && docs.len() >= limit {
+                break;
+            }
         }
+
+        Ok(docs)
     }
-}
 
-impl<'a> Collector for CurrentIndexCollector<'a> {
-    fn set_segment(&mut self, segment_id: SegmentLocalId, _: &SegmentReader) -> Result<(), TantivyError> {
-        self.collector.set_segment_id(segment_id);
+    /**
+    Get every document across all indexes, up to the limit.
+
+    This uses an `AllQuery` directly rather than relying on how the query
+    parser treats `*`, so it's the reliable way to list recent logs.
+    */
+    pub fn all(&self, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes(), 0, limit, |_| Ok(Some(Box::new(AllQuery) as Box<Query>)))
+    }
+
+    /**
+    Search for documents within the given time range.
+
+    Indexes entirely outside the range are skipped without being searched.
+    Within the rest, documents with a date `timestamp` field only match if it's
+    between `start` and `end`, inclusive. Indexes without a date `timestamp` field
+    can only be pruned by when they received documents, so all of their documents
+    match if the index overlaps the range.
+    */
+    pub fn search_time_range(&self, query: &str, start: SystemTime, end: SystemTime, limit: usize) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        self.search_indexes(self.store.indexes_in_range(start, end), 0, limit, |index| {
+            let query = parse_query(index, &self.store.config().default_fields, query)?;
+
+            Ok(Some(within_time_range(index, query, start, end)))
+        })
+    }
+
+    /**
+    Get the schema of an index a hit came from.
+
+    Results from different indexes have different fields, so this can be used
+    to decide what columns to render for a particular hit.
+    */
+    pub fn schema_of(&self, index: IndexId) -> Option<Schema> {
+        self.store.index(index).map(|index| index.schema())
+    }
+
+    /**
+    Search for documents and write them to the given writer as a JSON array.
+
+    Each hit is written and flushed as soon as it's read from its index,
+    so the whole set of results doesn't need to be buffered first.
+    */
+    pub fn write_json(&self, query: &str, limit: usize, mut w: impl Write) -> Result<(), crate::Error> {
+        w.write_all(b"[")?;
+
+        for (i, doc) in self.search(query, limit)?.into_iter().enumerate() {
+            let doc = doc?;
+
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+
+            w.write_all(doc.as_bytes())?;
+            w.flush()?;
+        }
+
+        w.write_all(b"]")?;
+        w.flush()?;
+
         Ok(())
     }
 
-    fn collect(&mut self, doc: DocId, score: Score) {
-        self.collector.collect(self.index, doc, score);
+    fn search_indexes(&self, indexes: impl IntoIterator<Item = (IndexId, Index)>, offset: usize, limit: usize, query: impl Fn(&Index) -> Result<Option<Box<Query>>, crate::Error>) -> Result<impl IntoIterator<Item = Result<String, crate::Error>>, crate::Error> {
+        let hits = self.hits_in_indexes(indexes, offset, limit, query)?;
+
+        Ok(hits.into_iter().map(|hit| hit.map(|hit| hit.json)))
     }
 
-    fn requires_scoring(&self) -> bool {
-        true
+    fn hits_in_indexes(&self, indexes: impl IntoIterator<Item = (IndexId, Index)>, offset: usize, limit: usize, query: impl Fn(&Index) -> Result<Option<Box<Query>>, crate::Error>) -> Result<impl IntoIterator<Item = Result<Hit, crate::Error>>, crate::Error> {
+        self.hits_in_indexes_until(indexes, offset, limit, query, None, &Interrupt::never())
+    }
+
+    fn hits_in_indexes_until(&self, indexes: impl IntoIterator<Item = (IndexId, Index)>, offset: usize, limit: usize, query: impl Fn(&Index) -> Result<Option<Box<Query>>, crate::Error>, min_score: Option<Score>, interrupt: &Interrupt) -> Result<impl IntoIterator<Item = Result<Hit, crate::Error>>, crate::Error> {
+        let start = Instant::now();
+
+        let mut lookup = HashMap::new();
+        let mut indexes = indexes.into_iter().peekable();
+
+        // If nothing has been logged yet then there's nothing to search
+        // We bail early so the query isn't parsed and no collector is allocated
+        let docs: Vec<Doc> = if indexes.peek().is_none() {
+            Vec::new()
+        } else {
+            // Unlimited searches stay unlimited regardless of the offset
+            let limit = if limit == 0 { 0 } else { offset.saturating_add(limit) };
+            let mut collector = MultiIndexCollector::with_limit(limit).with_min_score(min_score);
+
+            // We collect results from all indexes into a single collector
+            for (id, index) in indexes {
+                if interrupt.is_interrupted() {
+                    return Err(Error::Cancelled);
+                }
+
+                let query = match query(&index)? {
+                    Some(query) => query,
+                    None => continue,
+                };
+
+                let mut collector = CurrentIndexCollector::begin(id.to_owned(), &mut collector).interruptible(interrupt);
+
+                self.store.load_searchers(id, &index)?;
+                let searcher = index.searcher();
+
+                searcher.search(&*query, &mut collector)?;
+
+                if collector.interrupted {
+                    return Err(Error::Cancelled);
+                }
+
+                lookup.insert(id, (index, searcher));
+            }
+
+            collector.top_docs().into_iter().skip(offset).collect()
+        };
+
+        self.store.stats().record_search(start.elapsed());
+
+        Ok(self.hydrate(docs, lookup))
+    }
+
+    /**
+    Render collected docs as hits, reading them from the cache if possible.
+    */
+    fn hydrate<S>(&self, docs: Vec<Doc>, lookup: HashMap<IndexId, (Index, S)>) -> impl IntoIterator<Item = Result<Hit, crate::Error>>
+    where
+        S: Deref<Target = tantivy::Searcher>,
+    {
+        let cache = self.cache.clone();
+
+        let names: HashMap<IndexId, String> = lookup
+            .keys()
+            .filter_map(|id| self.store.index_name(*id).map(|name| (*id, name)))
+            .collect();
+
+        docs.into_iter().map(move |doc| {
+            let (ref index, ref searcher) = lookup[&doc.index];
+
+            // Segment ordinals are only stable for a single searcher, so the cache
+            // uses the segment's id instead
+            let key = (doc.index, searcher.segment_reader(doc.address.0).segment_id(), doc.address.1);
+            let hit = |json| Hit {
+                score: doc.score,
+                index: doc.index,
+                name: names.get(&doc.index).cloned(),
+                json,
+            };
+
+            if let Some(ref cache) = cache {
+                if let Some(json) = lock(&cache).get(key) {
+                    return Ok(hit(json));
+                }
+            }
+
+            let json = render(index, searcher, doc.address)?;
+
+            if let Some(ref cache) = cache {
+                lock(&cache).insert(key, json.clone());
+            }
+
+            Ok(hit(json))
+        })
+    }
+}
+
+/**
+A document matched by a search.
+
+Hits carry the id and name of the index they came from, so documents
+with different shapes can be told apart even if they share field names.
+*/
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub score: Score,
+    pub index: IndexId,
+    /**
+    The human-readable name of the index, describing its fields.
+    */
+    pub name: Option<String>,
+    pub json: String,
+}
+
+/**
+A document matched by a search along with a snippet of its message.
+*/
+#[derive(Debug, Clone)]
+pub struct Highlighted {
+    pub hit: Hit,
+    pub snippet: Option<String>,
+}
+
+/**
+Render a document as JSON.
+
+Bytes and float fields aren't stored, so they're read from their fast
+fields instead. Bytes are rendered as base64 strings and floats are
+decoded back into their original values.
+*/
+fn render(index: &Index, searcher: &tantivy::Searcher, address: DocAddress) -> Result<String, crate::Error> {
+    let schema = index.schema();
+    let doc = searcher.doc(address)?;
+
+    let fast_fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| match *field.field_type() {
+            FieldType::Bytes => true,
+            _ => is_float_field(field),
+        })
+        .map(|(field, _)| Field(field as u32))
+        .collect();
+
+    let bool_fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| is_bool_field(field))
+        .map(|(field, _)| Field(field as u32))
+        .collect();
+
+    if fast_fields.is_empty() && bool_fields.is_empty() {
+        return Ok(schema.to_json(&doc));
+    }
+
+    let mut json = serde_json::to_value(schema.to_named_doc(&doc))?;
+    let reader = searcher.segment_reader(address.0);
+
+    for field in bool_fields {
+        if let Some(serde_json::Value::Array(values)) = json.get_mut(schema.get_field_name(field)) {
+            for value in values.iter_mut() {
+                *value = to_bool(value.take());
+            }
+        }
+    }
+
+    for field in fast_fields {
+        let values = match *schema.get_field_entry(field).field_type() {
+            FieldType::Bytes => {
+                let values = reader.bytes_fast_field_reader(field).map_err(TantivyError::from)?;
+
+                vec![serde_json::Value::String(base64::encode(values.get_val(address.1)))]
+            },
+            _ => FloatValues::open(reader, field)?
+                .get(address.1)
+                .into_iter()
+                .map(|value| serde_json::Value::from(u64_to_f64(value)))
+                .collect(),
+        };
+
+        json[schema.get_field_name(field)] = serde_json::Value::Array(values);
+    }
+
+    Ok(json.to_string())
+}
+
+/**
+Convert the value of a boolean field back into a JSON boolean.
+
+Booleans are indexed as `0` or `1`, or as `false` or `true` text in older indexes.
+*/
+fn to_bool(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Number(ref n) if n.as_u64() == Some(0) => serde_json::Value::Bool(false),
+        serde_json::Value::Number(ref n) if n.as_u64() == Some(1) => serde_json::Value::Bool(true),
+        serde_json::Value::String(ref s) if s == "false" => serde_json::Value::Bool(false),
+        serde_json::Value::String(ref s) if s == "true" => serde_json::Value::Bool(true),
+        value => value,
+    }
+}
+
+/**
+Convert a rendered document back into the shape it was indexed from.
+*/
+fn unflatten(schema: &Schema, separator: &str, doc: serde_json::Value) -> serde_json::Value {
+    let mut root = serde_json::Map::new();
+
+    let fields = match doc {
+        serde_json::Value::Object(fields) => fields,
+        doc => return doc,
+    };
+
+    for (name, values) in fields {
+        let mut values = match values {
+            serde_json::Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        let is_bool = schema.get_field(&name).map(|field| is_bool_field(schema.get_field_entry(field))).unwrap_or(false);
+        if is_bool {
+            values = values.into_iter().map(to_bool).collect();
+        }
+
+        let value = if values.len() == 1 {
+            values.pop().expect("missing value")
+        } else {
+            serde_json::Value::Array(values)
+        };
+
+        let mut path: Vec<&str> = name.split(separator).collect();
+        let last = path.pop().expect("empty field name");
+
+        let mut target = &mut root;
+        for component in path {
+            let next = target.entry(component.to_owned()).or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+
+            if !next.is_object() {
+                *next = serde_json::Value::Object(serde_json::Map::new());
+            }
+
+            target = next.as_object_mut().expect("missing object");
+        }
+
+        target.insert(last.to_owned(), value);
+    }
+
+    serde_json::Value::Object(root)
+}
+
+type CacheKey = (IndexId, SegmentId, DocId);
+
+/**
+A small least-recently-used cache of rendered documents.
+*/
+struct DocCache {
+    capacity: usize,
+    docs: HashMap<CacheKey, String>,
+    order: VecDeque<CacheKey>,
+    hits: usize,
+}
+
+impl DocCache {
+    fn new(capacity: usize) -> Self {
+        DocCache {
+            capacity,
+            docs: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+        }
+    }
+
+    fn get(&mut self, key: CacheKey) -> Option<String> {
+        let doc = self.docs.get(&key).cloned()?;
+
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key);
+        self.hits += 1;
+
+        Some(doc)
+    }
+
+    fn insert(&mut self, key: CacheKey, doc: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.docs.insert(key, doc).is_none() {
+            self.order.push_back(key);
+        }
+
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.docs.remove(&evicted);
+            }
+        }
+    }
+}
+
+/**
+A structured filter over the fields in an index.
+
+Each term in the filter must match. Values are parsed using the field's
+own type and tokenizer, so `level` matches `ERROR` the same way a query
+for `level:error` would.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct QuerySpec {
+    terms: Vec<(String, String)>,
+    float_ranges: Vec<(String, f64, f64)>,
+    date_ranges: Vec<(String, SystemTime, SystemTime)>,
+}
+
+impl QuerySpec {
+    pub fn new() -> Self {
+        QuerySpec::default()
+    }
+
+    pub fn term(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.terms.push((field.into(), value.into()));
+        self
+    }
+
+    /**
+    Match float values between `start` and `end`, inclusive.
+
+    Indexes where the field doesn't hold floats don't match.
+    */
+    pub fn float_range(mut self, field: impl Into<String>, start: f64, end: f64) -> Self {
+        self.float_ranges.push((field.into(), start, end));
+        self
+    }
+
+    /**
+    Match dates between `start` and `end`, inclusive.
+
+    Dates are indexed as signed millisecond timestamps, so indexes where
+    the field doesn't hold signed values don't match.
+    */
+    pub fn date_range(mut self, field: impl Into<String>, start: SystemTime, end: SystemTime) -> Self {
+        self.date_ranges.push((field.into(), start, end));
+        self
+    }
+
+    /**
+    Compile the filter into a query for the given index.
+
+    If the index doesn't have one of the fields in the filter then `None` is returned.
+    */
+    fn compile(&self, index: &Index) -> Result<Option<Box<Query>>, crate::Error> {
+        let schema = index.schema();
+        let mut clauses = Vec::new();
+
+        for (field, value) in &self.terms {
+            let field = match schema.get_field(field) {
+                Some(field) => field,
+                None => return Ok(None),
+            };
+
+            let query_parser = QueryParser::for_index(index, vec![field]);
+            let query = query_parser.parse_query(value).map_err(|e| Error::QueryParse(format!("{:?}", e)))?;
+
+            clauses.push((Occur::Must, query));
+        }
+
+        for (field, start, end) in &self.float_ranges {
+            let field = match schema.get_field(field) {
+                Some(field) if is_float_field(schema.get_field_entry(field)) => field,
+                _ => return Ok(None),
+            };
+
+            let query: Box<Query> = Box::new(RangeQuery::new_u64_bounds(
+                field,
+                Bound::Included(f64_to_u64(*start)),
+                Bound::Included(f64_to_u64(*end)),
+            ));
+
+            clauses.push((Occur::Must, query));
+        }
+
+        for (field, start, end) in &self.date_ranges {
+            let field = match schema.get_field(field) {
+                Some(field) => match *schema.get_field_entry(field).field_type() {
+                    FieldType::I64(_) => field,
+                    _ => return Ok(None),
+                },
+                None => return Ok(None),
+            };
+
+            let query: Box<Query> = Box::new(RangeQuery::new_i64_bounds(
+                field,
+                Bound::Included(to_millis(*start)),
+                Bound::Included(to_millis(*end)),
+            ));
+
+            clauses.push((Occur::Must, query));
+        }
+
+        Ok(Some(Box::new(BooleanQuery::from(clauses))))
+    }
+}
+
+/**
+Restrict a query to documents whose `timestamp` date is within a range.
+
+If the index doesn't have a date `timestamp` field then the query is returned unchanged.
+*/
+fn within_time_range(index: &Index, query: Box<Query>, start: SystemTime, end: SystemTime) -> Box<Query> {
+    let schema = index.schema();
+
+    let field = match schema.get_field(TIMESTAMP_FIELD) {
+        Some(field) => match *schema.get_field_entry(field).field_type() {
+            FieldType::I64(_) => field,
+            _ => return query,
+        },
+        None => return query,
+    };
+
+    let range: Box<Query> = Box::new(RangeQuery::new_i64_bounds(
+        field,
+        Bound::Included(to_millis(start)),
+        Bound::Included(to_millis(end)),
+    ));
+
+    Box::new(BooleanQuery::from(vec![(Occur::Must, query), (Occur::Must, range)]))
+}
+
+/**
+A query built from terms and ranges instead of a query string.
+
+Fields are resolved against each index's schema when searching, so the same
+query can be run over indexes with different shapes. A term or range on a
+field that an index doesn't have doesn't match anything in that index.
+*/
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    clause: Clause,
+}
+
+#[derive(Debug, Clone)]
+enum Clause {
+    Term(String, String),
+    Range(String, i64, i64),
+    Exists(String),
+    Prefix(String, String),
+    Fuzzy(String, String, u8),
+    LevelAtLeast(Level),
+    And(Vec<QueryBuilder>),
+    Or(Vec<QueryBuilder>),
+}
+
+impl QueryBuilder {
+    /**
+    Match a value in a field.
+
+    Values are tokenized the same way as the field, so multiple tokens are
+    matched as a phrase. Values for numeric fields are parsed as numbers.
+    */
+    pub fn term(field: impl Into<String>, value: impl Into<String>) -> Self {
+        QueryBuilder {
+            clause: Clause::Term(field.into(), value.into()),
+        }
+    }
+
+    /**
+    Match numeric values between `start` and `end`, inclusive.
+    */
+    pub fn range(field: impl Into<String>, start: i64, end: i64) -> Self {
+        QueryBuilder {
+            clause: Clause::Range(field.into(), start, end),
+        }
+    }
+
+    /**
+    Match text values that start with a prefix.
+
+    Prefixes are matched against the terms in a field, so they suit fields that
+    keep their values whole, like paths and keywords. Fields that are split into
+    words match words that start with the prefix, ignoring case.
+    */
+    pub fn prefix(field: impl Into<String>, prefix: impl Into<String>) -> Self {
+        QueryBuilder {
+            clause: Clause::Prefix(field.into(), prefix.into()),
+        }
+    }
+
+    /**
+    Match text values that are within `max_edits` of a term.
+
+    Like prefixes, terms are matched against the terms in a field, ignoring
+    case for fields that are split into words. Edit distances above `2` are
+    treated as `2`.
+    */
+    pub fn fuzzy(field: impl Into<String>, term: impl Into<String>, max_edits: u8) -> Self {
+        QueryBuilder {
+            clause: Clause::Fuzzy(field.into(), term.into(), max_edits.min(MAX_FUZZY_EDITS)),
+        }
+    }
+
+    /**
+    Match documents that have a value for a field.
+
+    Null values aren't indexed, so they don't count as a value.
+    Fields that aren't indexed, like bytes, can't be checked, so they never match.
+    */
+    pub fn exists(field: impl Into<String>) -> Self {
+        QueryBuilder {
+            clause: Clause::Exists(field.into()),
+        }
+    }
+
+    /**
+    Match records logged at `level` or above.
+    */
+    pub fn level_at_least(level: Level) -> Self {
+        QueryBuilder {
+            clause: Clause::LevelAtLeast(level),
+        }
+    }
+
+    /**
+    Match documents that match both this query and `other`.
+    */
+    pub fn and(self, other: QueryBuilder) -> Self {
+        match self.clause {
+            Clause::And(mut queries) => {
+                queries.push(other);
+
+                QueryBuilder {
+                    clause: Clause::And(queries),
+                }
+            },
+            clause => QueryBuilder {
+                clause: Clause::And(vec![QueryBuilder { clause }, other]),
+            },
+        }
+    }
+
+    /**
+    Match documents that match either this query or `other`.
+    */
+    pub fn or(self, other: QueryBuilder) -> Self {
+        match self.clause {
+            Clause::Or(mut queries) => {
+                queries.push(other);
+
+                QueryBuilder {
+                    clause: Clause::Or(queries),
+                }
+            },
+            clause => QueryBuilder {
+                clause: Clause::Or(vec![QueryBuilder { clause }, other]),
+            },
+        }
+    }
+
+    /**
+    Compile the query for the given index.
+
+    If the query can't match anything in the index then `None` is returned.
+    */
+    fn compile(&self, index: &Index) -> Result<Option<Box<Query>>, crate::Error> {
+        let schema = index.schema();
+
+        match self.clause {
+            Clause::Term(ref field, ref value) => {
+                let field = match schema.get_field(field) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                let mut terms = match *schema.get_field_entry(field).field_type() {
+                    FieldType::Str(ref options) => {
+                        let tokenizer = options
+                            .get_indexing_options()
+                            .and_then(|indexing| index.tokenizers().get(indexing.tokenizer()));
+
+                        let tokenizer = match tokenizer {
+                            Some(tokenizer) => tokenizer,
+                            None => return Ok(None),
+                        };
+
+                        let mut terms = Vec::new();
+                        let mut tokens = tokenizer.token_stream(value);
+                        while tokens.advance() {
+                            terms.push(Term::from_field_text(field, &tokens.token().text));
+                        }
+
+                        terms
+                    },
+                    FieldType::U64(_) if is_float_field(schema.get_field_entry(field)) => match value.parse::<f64>() {
+                        Ok(value) => vec![Term::from_field_u64(field, f64_to_u64(value))],
+                        Err(_) => return Ok(None),
+                    },
+                    FieldType::U64(_) if is_bool_field(schema.get_field_entry(field)) => match &**value {
+                        "true" | "1" => vec![Term::from_field_u64(field, 1)],
+                        "false" | "0" => vec![Term::from_field_u64(field, 0)],
+                        _ => return Ok(None),
+                    },
+                    FieldType::U64(_) => match value.parse::<u64>() {
+                        Ok(value) => vec![Term::from_field_u64(field, value)],
+                        Err(_) => return Ok(None),
+                    },
+                    FieldType::I64(_) => match value.parse::<i64>() {
+                        Ok(value) => vec![Term::from_field_i64(field, value)],
+                        Err(_) => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+
+                let query: Box<Query> = match terms.len() {
+                    0 => return Ok(None),
+                    1 => Box::new(TermQuery::new(terms.pop().expect("missing term"), IndexRecordOption::Basic)),
+                    _ => Box::new(PhraseQuery::new(terms)),
+                };
+
+                Ok(Some(query))
+            },
+            Clause::Range(ref field, start, end) => {
+                let field = match schema.get_field(field) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                let query: Box<Query> = match *schema.get_field_entry(field).field_type() {
+                    FieldType::U64(_) if is_float_field(schema.get_field_entry(field)) => Box::new(RangeQuery::new_u64_bounds(
+                        field,
+                        Bound::Included(f64_to_u64(start as f64)),
+                        Bound::Included(f64_to_u64(end as f64)),
+                    )),
+                    FieldType::U64(_) => {
+                        // Unsigned fields can't hold anything below zero
+                        if end < 0 {
+                            return Ok(None);
+                        }
+
+                        Box::new(RangeQuery::new_u64_bounds(
+                            field,
+                            Bound::Included(start.max(0) as u64),
+                            Bound::Included(end as u64),
+                        ))
+                    },
+                    FieldType::I64(_) => Box::new(RangeQuery::new_i64_bounds(
+                        field,
+                        Bound::Included(start),
+                        Bound::Included(end),
+                    )),
+                    _ => return Ok(None),
+                };
+
+                Ok(Some(query))
+            },
+            Clause::Exists(ref field) => {
+                let field = match schema.get_field(field) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                // Documents in an index usually all have the same fields, but documents
+                // copied into a widened index don't have the fields it was widened with.
+                // An unbounded range only matches documents with some value for the field
+                let query: Box<Query> = match *schema.get_field_entry(field).field_type() {
+                    FieldType::U64(ref options) if options.is_indexed() => Box::new(RangeQuery::new_u64_bounds(field, Bound::Unbounded, Bound::Unbounded)),
+                    FieldType::I64(ref options) if options.is_indexed() => Box::new(RangeQuery::new_i64_bounds(field, Bound::Unbounded, Bound::Unbounded)),
+                    FieldType::Str(ref options) if options.get_indexing_options().is_some() => Box::new(RangeQuery::new_str_bounds(field, Bound::Unbounded, Bound::Unbounded)),
+                    // Fields that aren't indexed, like bytes and sources, have no terms to check
+                    _ => return Ok(None),
+                };
+
+                Ok(Some(query))
+            },
+            Clause::Prefix(ref field, ref prefix) => {
+                let field = match schema.get_field(field) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                let prefix = match *schema.get_field_entry(field).field_type() {
+                    FieldType::Str(ref options) => match options.get_indexing_options() {
+                        // The default tokenizer lowercases words
+                        Some(indexing) if is_lowercased(indexing.tokenizer()) => prefix.to_lowercase(),
+                        Some(_) => prefix.to_owned(),
+                        None => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+
+                // Every term that starts with the prefix sorts between it and
+                // the prefix followed by the largest char
+                let end = format!("{}{}", prefix, std::char::MAX);
+
+                Ok(Some(Box::new(RangeQuery::new_str_bounds(field, Bound::Included(&prefix), Bound::Included(&end)))))
+            },
+            Clause::Fuzzy(ref field, ref term, max_edits) => {
+                let field = match schema.get_field(field) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                let term = match *schema.get_field_entry(field).field_type() {
+                    FieldType::Str(ref options) => match options.get_indexing_options() {
+                        Some(indexing) if is_lowercased(indexing.tokenizer()) => term.to_lowercase(),
+                        Some(_) => term.to_owned(),
+                        None => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+
+                Ok(Some(Box::new(FuzzyTermQuery::new(Term::from_field_text(field, &term), max_edits, true))))
+            },
+            Clause::LevelAtLeast(level) => {
+                let field = match schema.get_field(SEVERITY_FIELD) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                };
+
+                Ok(Some(Box::new(RangeQuery::new_u64_bounds(field, Bound::Included(severity(level)), Bound::Unbounded))))
+            },
+            Clause::And(ref queries) => {
+                let mut clauses = Vec::new();
+
+                for query in queries {
+                    match query.compile(index)? {
+                        Some(query) => clauses.push((Occur::Must, query)),
+                        None => return Ok(None),
+                    }
+                }
+
+                Ok(Some(Box::new(BooleanQuery::from(clauses))))
+            },
+            Clause::Or(ref queries) => {
+                let mut clauses = Vec::new();
+
+                for query in queries {
+                    if let Some(query) = query.compile(index)? {
+                        clauses.push((Occur::Should, query));
+                    }
+                }
+
+                if clauses.is_empty() {
+                    return Ok(None);
+                }
+
+                Ok(Some(Box::new(BooleanQuery::from(clauses))))
+            },
+        }
+    }
+}
+
+/**
+Parse a query string for an index.
+
+Terms that don't name a field are searched for in whichever of the
+default fields the index has. If there aren't any default fields then
+they're searched for in all of the index's text fields. If the index
+doesn't have any of those either then the query doesn't match anything.
+
+Values for float fields, like `latency:1.5` or `latency:[0.5 TO 2.5]`,
+are encoded the same way as indexed floats before the query is parsed.
+RFC3339 dates for signed fields, like `timestamp:[2020-01-01T00:00:00Z TO *]`,
+are converted into the millisecond timestamps that dates are indexed as.
+*/
+pub fn parse_query(index: &Index, default_fields: &[String], query: &str) -> Result<Box<Query>, crate::Error> {
+    // The query parser builds terms using the type of the field in the index's schema
+    // That means `id:5` is parsed as a `u64` for unsigned fields and an `i64` for signed ones
+    // A negative number against an unsigned field is an error rather than a silent non-match
+    let schema = index.schema();
+    let default_fields = if !default_fields.is_empty() {
+        default_fields.iter().filter_map(|field| schema.get_field(field)).collect()
+    } else {
+        schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| match *field.field_type() {
+                FieldType::Str(ref options) => options.get_indexing_options().is_some(),
+                _ => false,
+            })
+            .map(|(field, _)| Field(field as u32))
+            .collect()
+    };
+
+    let query_parser = QueryParser::for_index(index, default_fields);
+
+    match query_parser.parse_query(&translate_query(&schema, query)) {
+        Ok(query) => Ok(query),
+        // Bare terms can't match anything in an index without any fields to search them in
+        Err(QueryParserError::NoDefaultFieldDeclared) => Ok(Box::new(BooleanQuery::from(Vec::new()))),
+        Err(e) => Err(Error::QueryParse(format!("{:?}", e))),
+    }
+}
+
+/**
+Rewrite the values of fields in a query string into the form they're indexed in.
+
+Tantivy's query parser only understands plain integers for numeric fields, so
+values that need translating, like floats, are replaced with the number they're
+indexed as. Terms, phrases, and both bounds of ranges are translated. Anything
+that can't be translated is left alone for the query parser to deal with.
+*/
+fn translate_query(schema: &Schema, query: &str) -> String {
+    let chars: Vec<char> = query.chars().collect();
+    let mut translated = String::with_capacity(query.len());
+    let mut i = 0;
+
+    // Find the end of a phrase or range that starts at `start`, including its closing character
+    let closing = |start: usize, close: &[char]| chars[start + 1..]
+        .iter()
+        .position(|c| close.contains(c))
+        .map(|len| start + 1 + len + 1);
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // Phrases without a field are copied as-is
+        if c == '"' {
+            let end = closing(i, &['"']).unwrap_or(chars.len());
+
+            translated.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let starts_field = c.is_alphabetic() && (i == 0 || match chars[i - 1] {
+            '(' | '+' | '-' => true,
+            prev => prev.is_whitespace(),
+        });
+
+        if !starts_field {
+            translated.push(c);
+            i += 1;
+            continue;
+        }
+
+        let name_end = chars[i..]
+            .iter()
+            .position(|c| !(c.is_alphanumeric() || *c == '_'))
+            .map(|len| i + len)
+            .unwrap_or(chars.len());
+        let name: String = chars[i..name_end].iter().collect();
+
+        translated.push_str(&name);
+        i = name_end;
+
+        let entry = match (chars.get(i), schema.get_field(&name)) {
+            (Some(':'), Some(field)) => schema.get_field_entry(field),
+            _ => continue,
+        };
+
+        translated.push(':');
+        i += 1;
+
+        match chars.get(i).cloned() {
+            // A range like `[a TO b]` or `{a TO b}`
+            Some(open) if open == '[' || open == '{' => {
+                let end = match closing(i, &[']', '}']) {
+                    Some(end) => end,
+                    None => {
+                        translated.extend(&chars[i..]);
+                        break;
+                    },
+                };
+                let range: String = chars[i + 1..end - 1].iter().collect();
+
+                match range.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    [lower, "TO", upper] => {
+                        let bound = |value: &str| match value {
+                            "*" => value.to_owned(),
+                            value => translate_value(entry, value).unwrap_or_else(|| value.to_owned()),
+                        };
+
+                        translated.push(open);
+                        translated.push_str(&format!("{} TO {}", bound(lower), bound(upper)));
+                        translated.push(chars[end - 1]);
+                    },
+                    _ => translated.extend(&chars[i..end]),
+                }
+
+                i = end;
+            },
+            // A phrase like `"a"`
+            Some('"') => {
+                let end = match closing(i, &['"']) {
+                    Some(end) => end,
+                    None => {
+                        translated.extend(&chars[i..]);
+                        break;
+                    },
+                };
+                let value: String = chars[i + 1..end - 1].iter().collect();
+
+                match translate_value(entry, &value) {
+                    Some(value) => translated.push_str(&value),
+                    None => translated.extend(&chars[i..end]),
+                }
+
+                i = end;
+            },
+            // A plain term like `a`
+            _ => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || *c == ')')
+                    .map(|len| i + len)
+                    .unwrap_or(chars.len());
+                let value: String = chars[i..end].iter().collect();
+
+                translated.push_str(&translate_value(entry, &value).unwrap_or(value));
+
+                i = end;
+            },
+        }
+    }
+
+    translated
+}
+
+/**
+Translate a value in a query string for a field into the form it's indexed in.
+
+If the value doesn't need translating then `None` is returned.
+*/
+fn translate_value(entry: &FieldEntry, value: &str) -> Option<String> {
+    if is_float_field(entry) {
+        return value.parse::<f64>().ok().map(|value| f64_to_u64(value).to_string());
+    }
+
+    // Dates are indexed in signed fields, but plain integers are left alone
+    if let FieldType::I64(_) = *entry.field_type() {
+        return parse_rfc3339(value).map(|millis| millis.to_string());
+    }
+
+    None
+}
+
+// NOTE: These types are pinched from tantivy directly
+// They've been tweaked to support an extra `IndexId` field
+
+#[derive(Clone, Copy)]
+struct Doc {
+    score: Score,
+    index: IndexId,
+    address: DocAddress,
+}
+
+impl PartialOrd for Doc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Doc {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Docs with the same score are ordered by where they came from
+        // so pages of results with ties don't overlap
+        other
+            .score
+            .partial_cmp(&self.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| (self.index, self.address).cmp(&(other.index, other.address)))
+    }
+}
+
+impl PartialEq for Doc {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Doc {}
+
+struct MultiIndexCollector {
+    // A limit of `None` collects every doc
+    limit: Option<usize>,
+    // Docs scoring below the minimum are ignored
+    min_score: Option<Score>,
+    heap: BinaryHeap<Doc>,
+    // Segment ids are only unique within an index,
+    // so the current segment is tracked along with the index it belongs to
+    segment: (IndexId, SegmentLocalId),
+}
+
+impl MultiIndexCollector {
+    fn with_limit(limit: usize) -> MultiIndexCollector {
+        let limit = if limit == 0 { None } else { Some(limit) };
+
+        MultiIndexCollector {
+            limit,
+            min_score: None,
+            heap: BinaryHeap::with_capacity(limit.unwrap_or(0)),
+            segment: (0, 0),
+        }
+    }
+
+    fn with_min_score(mut self, min_score: Option<Score>) -> MultiIndexCollector {
+        self.min_score = min_score;
+        self
+    }
+
+    fn top_docs(&self) -> impl IntoIterator<Item = Doc> {
+        let mut feature_docs: Vec<Doc> = self.heap.iter().cloned().collect();
+        feature_docs.sort();
+        feature_docs
+    }
+
+    #[inline]
+    fn at_capacity(&self) -> bool {
+        match self.limit {
+            Some(limit) => self.heap.len() >= limit,
+            None => false,
+        }
+    }
+
+    fn set_segment(&mut self, index: IndexId, segment_id: SegmentLocalId) {
+        self.segment = (index, segment_id);
+    }
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let (index, segment_id) = self.segment;
+
+        if let Some(min_score) = self.min_score {
+            if score < min_score {
+                return;
+            }
+        }
+
+        if self.at_capacity() {
+            // It's ok to unwrap because an unlimited collector is never at capacity
+            let limit_doc: Doc = self
+                .heap
+                .peek()
+                .expect("Collector with size 0 is forbidden")
+                .clone();
+            if limit_doc.score < score {
+                let mut mut_head = self
+                    .heap
+                    .peek_mut()
+                    .expect("Collector with size 0 is forbidden");
+                mut_head.score = score;
+                mut_head.index = index;
+                mut_head.address = DocAddress(segment_id, doc);
+            }
+        } else {
+            let wrapped_doc = Doc {
+                score,
+                index,
+                address: DocAddress(segment_id, doc),
+            };
+            self.heap.push(wrapped_doc);
+        }
+    }
+}
+
+/**
+A way to stop a search before it finishes.
+*/
+struct Interrupt<'a> {
+    cancel: Option<&'a AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Interrupt<'a> {
+    fn never() -> Self {
+        Interrupt {
+            cancel: None,
+            deadline: None,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.map(|cancel| cancel.load(AtomicOrdering::Relaxed)).unwrap_or(false)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false)
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.is_cancelled() || self.is_expired()
+    }
+}
+
+struct CurrentIndexCollector<'a> {
+    index: IndexId,
+    collector: &'a mut MultiIndexCollector,
+    interrupt: Option<&'a Interrupt<'a>>,
+    interrupted: bool,
+    seen: usize,
+}
+
+impl<'a> CurrentIndexCollector<'a> {
+    fn begin(index: IndexId, collector: &'a mut MultiIndexCollector) -> Self {
+        CurrentIndexCollector {
+            index,
+            collector,
+            interrupt: None,
+            interrupted: false,
+            seen: 0,
+        }
+    }
+
+    /**
+    Stop collecting documents once the search is interrupted.
+
+    Tantivy doesn't let a collector stop a search, so documents after the
+    interruption are still visited, but they're ignored.
+    */
+    fn interruptible(mut self, interrupt: &'a Interrupt<'a>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+}
+
+impl<'a> Collector for CurrentIndexCollector<'a> {
+    fn set_segment(&mut self, segment_id: SegmentLocalId, _: &SegmentReader) -> Result<(), TantivyError> {
+        if let Some(interrupt) = self.interrupt {
+            self.interrupted = self.interrupted || interrupt.is_interrupted();
+        }
+
+        self.collector.set_segment(self.index, segment_id);
+        Ok(())
+    }
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.interrupted {
+            return;
+        }
+
+        if let Some(interrupt) = self.interrupt {
+            // Checking the time is more expensive than checking the flag
+            // so it's only done every so often
+            self.seen += 1;
+            if interrupt.is_cancelled() || (self.seen % DEADLINE_CHECK_INTERVAL == 0 && interrupt.is_expired()) {
+                self.interrupted = true;
+                return;
+            }
+        }
+
+        self.collector.collect(doc, score);
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+}
+
+/**
+The type of fast field values to sort on.
+*/
+#[derive(Clone, Copy)]
+enum SortType {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+/**
+The type of values to aggregate.
+*/
+#[derive(Clone, Copy)]
+enum AggregateType {
+    Unsigned,
+    Signed,
+    Float,
+    Stored,
+}
+
+/**
+A collector that counts the distinct values of a field.
+
+Values of fast fields are counted as they're collected. Other fields
+can't be read from a segment cheaply, so their documents are collected
+and read afterwards.
+*/
+struct AggregateCollector<'a> {
+    field: Field,
+    ty: AggregateType,
+    counts: &'a mut HashMap<String, u64>,
+    stored: Vec<DocAddress>,
+    segment: SegmentLocalId,
+    values: Option<Box<dyn Fn(DocId) -> Vec<String>>>,
+}
+
+impl<'a> AggregateCollector<'a> {
+    fn begin(field: Field, ty: AggregateType, counts: &'a mut HashMap<String, u64>) -> Self {
+        AggregateCollector {
+            field,
+            ty,
+            counts,
+            stored: Vec::new(),
+            segment: 0,
+            values: None,
+        }
+    }
+}
+
+impl<'a> Collector for AggregateCollector<'a> {
+    fn set_segment(&mut self, segment_id: SegmentLocalId, reader: &SegmentReader) -> Result<(), TantivyError> {
+        let field = self.field;
+
+        self.segment = segment_id;
+        self.values = match self.ty {
+            AggregateType::Unsigned => {
+                let values = reader.fast_field_reader::<u64>(field).map_err(TantivyError::from)?;
+                Some(Box::new(move |doc: DocId| vec![values.get(doc).to_string()]))
+            },
+            AggregateType::Signed => {
+                let values = reader.fast_field_reader::<i64>(field).map_err(TantivyError::from)?;
+                Some(Box::new(move |doc: DocId| vec![values.get(doc).to_string()]))
+            },
+            AggregateType::Float => {
+                let values = FloatValues::open(reader, field)?;
+                Some(Box::new(move |doc: DocId| values.get(doc).into_iter().map(|value| u64_to_f64(value).to_string()).collect()))
+            },
+            AggregateType::Stored => None,
+        };
+
+        Ok(())
+    }
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        match self.values {
+            Some(ref values) => {
+                for value in values(doc) {
+                    *self.counts.entry(value).or_insert(0) += 1;
+                }
+            },
+            None => self.stored.push(DocAddress(self.segment, doc)),
+        }
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+}
+
+/**
+A document collected by the value of a field.
+
+Values are ranked so that larger ranks are always better, regardless of
+the sort direction. Ties are broken by where the document came from.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SortedDoc {
+    rank: i128,
+    origin: Reverse<(IndexId, DocAddress)>,
+}
+
+struct SortedCollector {
+    // A limit of `None` collects every doc
+    limit: Option<usize>,
+    ascending: bool,
+    heap: BinaryHeap<Reverse<SortedDoc>>,
+    segment: (IndexId, SegmentLocalId),
+    values: Option<Box<dyn Fn(DocId) -> i128>>,
+}
+
+impl SortedCollector {
+    fn with_limit(limit: usize, ascending: bool) -> SortedCollector {
+        let limit = if limit == 0 { None } else { Some(limit) };
+
+        SortedCollector {
+            limit,
+            ascending,
+            heap: BinaryHeap::with_capacity(limit.unwrap_or(0)),
+            segment: (0, 0),
+            values: None,
+        }
+    }
+
+    fn top_docs(self) -> Vec<Doc> {
+        let mut docs: Vec<SortedDoc> = self.heap.into_iter().map(|Reverse(doc)| doc).collect();
+        docs.sort_by(|a, b| b.cmp(a));
+
+        docs
+            .into_iter()
+            .map(|doc| {
+                let Reverse((index, address)) = doc.origin;
+
+                Doc {
+                    score: 0.0,
+                    index,
+                    address,
+                }
+            })
+            .collect()
+    }
+
+    fn set_segment(&mut self, index: IndexId, segment_id: SegmentLocalId, values: Option<Box<dyn Fn(DocId) -> i128>>) {
+        self.segment = (index, segment_id);
+        self.values = values;
+    }
+
+    fn collect(&mut self, doc: DocId) {
+        let (index, segment_id) = self.segment;
+
+        let rank = match (self.values.as_ref().map(|values| values(doc)), self.ascending) {
+            (Some(value), false) => value,
+            (None, false) => i128::min_value(),
+            (Some(value), true) => -value,
+            (None, true) => i128::max_value(),
+        };
+
+        let doc = SortedDoc {
+            rank,
+            origin: Reverse((index, DocAddress(segment_id, doc))),
+        };
+
+        if self.limit.map(|limit| self.heap.len() < limit).unwrap_or(true) {
+            self.heap.push(Reverse(doc));
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if doc > worst.0 {
+                *worst = Reverse(doc);
+            }
+        }
+    }
+}
+
+struct CurrentSortedIndexCollector<'a> {
+    index: IndexId,
+    field: Option<(Field, SortType)>,
+    collector: &'a mut SortedCollector,
+}
+
+impl<'a> CurrentSortedIndexCollector<'a> {
+    fn begin(index: IndexId, field: Option<(Field, SortType)>, collector: &'a mut SortedCollector) -> Self {
+        CurrentSortedIndexCollector {
+            index,
+            field,
+            collector,
+        }
+    }
+}
+
+impl<'a> Collector for CurrentSortedIndexCollector<'a> {
+    fn set_segment(&mut self, segment_id: SegmentLocalId, reader: &SegmentReader) -> Result<(), TantivyError> {
+        let values: Option<Box<dyn Fn(DocId) -> i128>> = match self.field {
+            Some((field, SortType::Unsigned)) => reader
+                .fast_field_reader::<u64>(field)
+                .ok()
+                .map(|values| Box::new(move |doc: DocId| values.get(doc) as i128) as Box<dyn Fn(DocId) -> i128>),
+            Some((field, SortType::Signed)) => reader
+                .fast_field_reader::<i64>(field)
+                .ok()
+                .map(|values| Box::new(move |doc: DocId| values.get(doc) as i128) as Box<dyn Fn(DocId) -> i128>),
+            // Encoded floats sort in the same order as the floats themselves
+            // Arrays of floats are sorted by their first element
+            Some((field, SortType::Float)) => FloatValues::open(reader, field)
+                .ok()
+                .map(|values| Box::new(move |doc: DocId| values.get(doc).first().map(|value| *value as i128).unwrap_or(0)) as Box<dyn Fn(DocId) -> i128>),
+            None => None,
+        };
+
+        self.collector.set_segment(self.index, segment_id, values);
+        Ok(())
+    }
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        self.collector.collect(doc);
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{
+            Duration,
+            UNIX_EPOCH,
+        },
+    };
+
+    use serde_derive::{
+        Deserialize,
+        Serialize,
+    };
+    use serde_json::json;
+
+    use crate::{
+        config::{
+            CommitPolicy,
+            Config,
+        },
+        index::Indexer,
+        schema::{
+            Date,
+            FieldHint,
+            Options,
+            Path,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn search_time_range_prunes_indexes() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "first": "a" })).expect("failed to index");
+        thread::sleep(Duration::from_millis(10));
+
+        let start = SystemTime::now();
+        thread::sleep(Duration::from_millis(10));
+
+        indexer.index(json!({ "second": "b" })).expect("failed to index");
+        thread::sleep(Duration::from_millis(10));
+
+        let end = SystemTime::now();
+        thread::sleep(Duration::from_millis(10));
+
+        indexer.index(json!({ "third": "c" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<_> = searcher
+            .search_time_range("*", start, end, 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert!(docs[0].contains("second"));
+    }
+
+    #[test]
+    fn search_time_range_filters_docs_by_timestamp() {
+        #[derive(Serialize)]
+        struct Record {
+            timestamp: Date,
+            id: u64,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // 2020-01-01T00:00:00Z
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        let an_hour = Duration::from_secs(60 * 60);
+
+        // Every record shares an index, so only some of its documents are in range
+        for id in 0..3 {
+            indexer.index(Record { timestamp: Date(midnight + an_hour * id), id: id as u64 }).expect("failed to index");
+        }
+
+        assert_eq!(1, store.indexes().into_iter().count());
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .search_time_range("*", midnight + an_hour / 2, midnight + an_hour * 3 / 2, 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(Some(1), docs[0]["id"][0].as_u64());
+    }
+
+    #[test]
+    fn all_returns_every_doc() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for id in 0..3 {
+            indexer.index(json!({ "id": id, "msg": "a record" })).expect("failed to index");
+        }
+
+        let searcher = Searcher::new(store);
+        let docs = searcher.all(10).expect("failed to search").into_iter().count();
+
+        assert_eq!(3, docs);
+    }
+
+    #[test]
+    fn all_returns_docs_of_every_shape() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "a record" })).expect("failed to index");
+        indexer.index(json!({ "id": 1 })).expect("failed to index");
+        indexer.index(json!({ "latency": 1.5, "ok": true })).expect("failed to index");
+        indexer.index(json!({ "data": { "nested": [1, 2] } })).expect("failed to index");
+
+        // None of the indexes share a text field that a query string could default to
+        let searcher = Searcher::new(store.clone());
+        let docs = searcher.all(0).expect("failed to search").into_iter().collect::<Result<Vec<_>, _>>().expect("failed to read docs");
+
+        assert_eq!(4, store.indexes().into_iter().count());
+        assert_eq!(4, docs.len());
+    }
+
+    #[test]
+    fn schema_of_reports_fields_per_index() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "a": 1, "msg": "a record" });
+        let b = json!({ "b": "text" });
+
+        let a_id = crate::schema::Doc::build(&a).expect("failed to build doc").index();
+        let b_id = crate::schema::Doc::build(&b).expect("failed to build doc").index();
+
+        indexer.index(a).expect("failed to index");
+        indexer.index(b).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let fields = |id| {
+            let mut fields: Vec<String> = searcher
+                .schema_of(id)
+                .expect("missing index")
+                .fields()
+                .iter()
+                .map(|field| field.name().to_owned())
+                .collect();
+
+            fields.sort();
+            fields
+        };
+
+        assert_eq!(vec!["a", "msg"], fields(a_id));
+        assert_eq!(vec!["b"], fields(b_id));
+    }
+
+    #[test]
+    fn path_fields_match_exactly_and_by_parent() {
+        #[derive(Serialize)]
+        struct WithPath {
+            path: Path<&'static str>,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(WithPath { path: Path("./monkey-path/logs/a.log") }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let count = |query| searcher.search(query, 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, count(r#"path:"./monkey-path/logs/a.log""#));
+        assert_eq!(1, count(r#"path:"./monkey-path""#));
+        assert_eq!(0, count(r#"path:"./monkey""#));
+    }
+
+    #[test]
+    fn cached_docs_are_reused_across_commits() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "a record" })).expect("failed to index");
+
+        let searcher = Searcher::with_cache(store, 10);
+        let search = || searcher.search("msg:record", 10).expect("failed to search").into_iter().map(|doc| doc.expect("failed to read doc")).collect::<Vec<_>>();
+
+        let first = search();
+        assert_eq!(0, searcher.cache_hits());
+
+        let second = search();
+        assert_eq!(1, searcher.cache_hits());
+        assert_eq!(first, second);
+
+        indexer.index(json!({ "msg": "another record" })).expect("failed to index");
+
+        // The first document's segment hasn't changed, so it's still cached
+        let third = search();
+        assert_eq!(2, third.len());
+        assert_eq!(2, searcher.cache_hits());
+    }
+
+    #[test]
+    fn search_filtered_combines_query_and_filter() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "level": "ERROR", "msg": "timeout connecting" })).expect("failed to index");
+        indexer.index(json!({ "level": "INFO", "msg": "timeout connecting" })).expect("failed to index");
+        indexer.index(json!({ "level": "ERROR", "msg": "all good" })).expect("failed to index");
+        indexer.index(json!({ "msg": "timeout without a level" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<_> = searcher
+            .search_filtered("msg:timeout", &QuerySpec::new().term("level", "error"), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert!(docs[0].contains("timeout connecting"));
+        assert!(docs[0].contains("ERROR"));
+    }
+
+    #[test]
+    fn searching_an_empty_store_is_empty() {
+        let searcher = Searcher::new(Store::new());
+
+        // The query isn't valid, but there's nothing to parse it against
+        let docs = searcher.search("msg:(", 10).expect("failed to search").into_iter().count();
+        assert_eq!(0, docs);
+
+        let docs = searcher.all(10).expect("failed to search").into_iter().count();
+        assert_eq!(0, docs);
+    }
+
+    #[test]
+    fn numeric_queries_respect_field_types() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "id": 5u64 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let docs = searcher.search("id:5", 10).expect("failed to search").into_iter().count();
+        assert_eq!(1, docs);
+
+        // `id` is unsigned, so a negative value can't be parsed for it
+        match searcher.search("id:-5", 10) {
+            Err(Error::QueryParse(_)) => (),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected a negative unsigned value to fail to parse"),
+        }
+    }
+
+    #[test]
+    fn search_dedup_keeps_one_hit_per_value() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for _ in 0..3 {
+            indexer.index(json!({ "request_id": "abc", "msg": "timeout" })).expect("failed to index");
+        }
+
+        indexer.index(json!({ "request_id": "def", "msg": "timeout" })).expect("failed to index");
+        indexer.index(json!({ "msg": "timeout" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs = searcher.search_dedup("msg:timeout", "request_id", 10).expect("failed to search");
+
+        assert_eq!(3, docs.len());
+        assert_eq!(1, docs.iter().filter(|doc| doc.contains("abc")).count());
+    }
+
+    #[test]
+    fn bytes_fields_are_rendered_as_base64() {
+        struct Bytes(&'static [u8]);
+
+        impl serde::Serialize for Bytes {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_bytes(self.0)
+            }
+        }
+
+        #[derive(Serialize)]
+        struct WithBytes {
+            data: Bytes,
+            msg: &'static str,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(WithBytes { data: Bytes(b"hello"), msg: "with bytes" }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<_> = searcher
+            .search("msg:bytes", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert!(docs[0].contains("aGVsbG8="));
+
+        // Bytes aren't indexed, so they can't be checked for
+        assert_eq!(0, searcher.has_field("data", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn write_json_streams_an_array_of_hits() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "a": "one" })).expect("failed to index");
+        indexer.index(json!({ "a": "two" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let mut empty = Vec::new();
+        searcher.write_json("a:three", 10, &mut empty).expect("failed to write");
+
+        assert_eq!("[]", String::from_utf8(empty).expect("invalid utf8"));
+
+        let mut hits = Vec::new();
+        searcher.write_json("*", 10, &mut hits).expect("failed to write");
+
+        let hits: Vec<serde_json::Value> = serde_json::from_slice(&hits).expect("invalid json");
+        let mut values: Vec<_> = hits.iter().map(|hit| hit["a"][0].as_str().expect("missing field").to_owned()).collect();
+        values.sort();
+
+        assert_eq!(vec!["one", "two"], values);
+    }
+
+    #[test]
+    fn unsigned_fields_round_trip_above_i64_max() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "n": u64::max_value() })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .all(10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(Some(u64::max_value()), hits[0]["n"][0].as_u64());
+    }
+
+    #[test]
+    fn float_values_in_query_strings_match_by_value() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "latency": 1.5, "id": 1 })).expect("failed to index");
+        indexer.index(json!({ "latency": -0.25, "id": 2 })).expect("failed to index");
+        indexer.index(json!({ "latency": 3.0, "id": 3 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let ids = |query: &str| {
+            let mut ids: Vec<u64> = searcher
+                .search(query, 10)
+                .expect("failed to search")
+                .into_iter()
+                .map(|doc| serde_json::from_str::<serde_json::Value>(&doc.expect("failed to read doc")).expect("invalid json")["id"][0].as_u64().expect("missing id"))
+                .collect();
+
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(vec![1], ids("latency:1.5"));
+        assert_eq!(vec![1], ids("latency:\"1.5\""));
+        assert_eq!(vec![1], ids("latency:[0.5 TO 2.5]"));
+        assert_eq!(vec![1, 2], ids("latency:[-1 TO 2]"));
+        assert_eq!(vec![2], ids("latency:{* TO 1.5}"));
+        assert_eq!(vec![1, 3], ids("latency:[0 TO *] AND NOT id:2"));
+    }
+
+    #[test]
+    fn float_ranges_match_by_value() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "latency": 1.5, "id": 1 })).expect("failed to index");
+        indexer.index(json!({ "latency": -0.25, "id": 2 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let in_range: Vec<serde_json::Value> = searcher
+            .search_filtered("*", &QuerySpec::new().float_range("latency", 0.5, 2.0), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, in_range.len());
+        assert_eq!(Some(1), in_range[0]["id"][0].as_u64());
+
+        let negative = searcher
+            .search_filtered("*", &QuerySpec::new().float_range("latency", -1.0, 0.0), 10)
+            .expect("failed to search")
+            .into_iter()
+            .count();
+
+        assert_eq!(1, negative);
+    }
+
+    #[test]
+    fn date_ranges_match_by_time() {
+        #[derive(Serialize)]
+        struct Record {
+            timestamp: Date,
+            id: u64,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // 2020-01-01T00:00:00Z
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        let an_hour_later = midnight + Duration::from_secs(60 * 60);
+
+        indexer.index(Record { timestamp: Date(midnight), id: 1 }).expect("failed to index");
+        indexer.index(Record { timestamp: Date(an_hour_later), id: 2 }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let all = searcher
+            .search_filtered("*", &QuerySpec::new().date_range("timestamp", midnight, SystemTime::now()), 10)
+            .expect("failed to search")
+            .into_iter()
+            .count();
+
+        assert_eq!(2, all);
+
+        let later: Vec<serde_json::Value> = searcher
+            .search_filtered("*", &QuerySpec::new().date_range("timestamp", midnight + Duration::from_secs(30 * 60), SystemTime::now()), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, later.len());
+        assert_eq!(Some(2), later[0]["id"][0].as_u64());
+    }
+
+    #[test]
+    fn rfc3339_dates_in_query_strings_match_by_time() {
+        #[derive(Serialize)]
+        struct Record {
+            timestamp: Date,
+            id: u64,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // 2020-01-01T00:00:00Z
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        let an_hour_later = midnight + Duration::from_secs(60 * 60);
+
+        indexer.index(Record { timestamp: Date(midnight), id: 1 }).expect("failed to index");
+        indexer.index(Record { timestamp: Date(an_hour_later), id: 2 }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let ids = |query: &str| {
+            let mut ids: Vec<u64> = searcher
+                .search(query, 10)
+                .expect("failed to search")
+                .into_iter()
+                .map(|doc| serde_json::from_str::<serde_json::Value>(&doc.expect("failed to read doc")).expect("invalid json")["id"][0].as_u64().expect("missing id"))
+                .collect();
+
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(vec![1, 2], ids("timestamp:[2020-01-01T00:00:00Z TO *]"));
+        assert_eq!(vec![2], ids("timestamp:[2020-01-01T00:30:00Z TO *]"));
+        assert_eq!(vec![2], ids("timestamp:[2020-01-01T10:30:00+10:00 TO 2020-01-01T01:00:00Z]"));
+        assert_eq!(vec![1], ids("timestamp:\"2020-01-01T00:00:00.000Z\""));
+
+        // Plain integers are still millisecond timestamps
+        assert_eq!(vec![1], ids("timestamp:1577836800000"));
+    }
+
+    #[test]
+    fn system_times_are_indexed_as_dates() {
+        #[derive(Serialize)]
+        struct Record {
+            at: SystemTime,
+            took: Duration,
+        }
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // 2020-01-01T00:00:00Z
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+
+        indexer.index(Record { at: midnight, took: Duration::from_millis(1_500) }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let hits: Vec<serde_json::Value> = searcher
+            .search_filtered("*", &QuerySpec::new().date_range("at", midnight, midnight + Duration::from_secs(1)), 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(Some(1_500), hits[0]["took"][0].as_u64());
+    }
+
+    #[test]
+    fn numeric_fields_are_returned_in_hits() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "id": 7, "offset": -3, "ratio": 0.5, "name": "x" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .search("name:x", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(Some(7), hits[0]["id"][0].as_u64());
+        assert_eq!(Some(-3), hits[0]["offset"][0].as_i64());
+        assert_eq!(Some(0.5), hits[0]["ratio"][0].as_f64());
+    }
+
+    #[test]
+    fn numeric_terms_match_a_single_record() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for id in 1..=3 {
+            indexer.index(json!({ "id": id, "msg": "a record" })).expect("failed to index");
+        }
+
+        let searcher = Searcher::new(store);
+        let docs = searcher.search("id:2", 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, docs);
+    }
+
+    #[test]
+    fn evicted_docs_keep_their_own_index() {
+        let mut collector = MultiIndexCollector::with_limit(1);
+
+        collector.set_segment(1, 0);
+        collector.collect(0, 1.0);
+
+        collector.set_segment(2, 0);
+        collector.collect(0, 2.0);
+
+        let docs: Vec<_> = collector.top_docs().into_iter().collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(2, docs[0].index);
+    }
+
+    #[test]
+    fn hits_are_read_from_the_index_they_were_collected_in() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // The shorter message scores higher, so whichever index is searched
+        // first the best hit is always the one with the `extra` field
+        indexer.index(json!({ "msg": "apple pear pear pear pear pear" })).expect("failed to index");
+        indexer.index(json!({ "msg": "apple", "extra": "field" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .search("msg:apple", 1)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(Some("field"), hits[0]["extra"][0].as_str());
+    }
+
+    #[test]
+    fn hits_are_read_from_the_segment_they_were_collected_in() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        // Each commit creates a new segment in each index
+        for value in &["one", "two", "three"] {
+            indexer.index(json!({ "msg": format!("a {}", value) })).expect("failed to index");
+            indexer.index(json!({ "msg": format!("a {}", value), "extra": *value })).expect("failed to index");
+        }
+
+        let segments = store.indexes().into_iter().map(|(_, index)| index.searchable_segment_ids().expect("failed to get segments").len()).collect::<Vec<_>>();
+        assert_eq!(vec![3, 3], segments);
+
+        let searcher = Searcher::new(store);
+        let mut hits: Vec<String> = searcher
+            .search("msg:a", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| {
+                let doc: serde_json::Value = serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json");
+                let msg = doc["msg"][0].as_str().expect("missing msg");
+
+                // Docs with an `extra` field must have a matching message
+                if let Some(extra) = doc["extra"][0].as_str() {
+                    assert_eq!(format!("a {}", extra), msg);
+                }
+
+                msg.to_owned()
+            })
+            .collect();
+
+        hits.sort();
+
+        assert_eq!(vec!["a one", "a one", "a three", "a three", "a two", "a two"], hits);
+    }
+
+    #[test]
+    fn search_paged_returns_pages_without_overlap() {
+        // Commit all docs at once so they land in a single segment
+        let store = Store::with_config(Config::new().commit(CommitPolicy::Batch { docs: 25, interval: Duration::from_secs(60) }));
+        let mut indexer = Indexer::new(store.clone());
+
+        for n in 0..25 {
+            indexer.index(json!({ "msg": "a record", "n": n })).expect("failed to index");
+        }
+
+        let searcher = Searcher::new(store);
+        let page = |offset| -> Vec<u64> {
+            searcher
+                .search_paged("msg:record", offset, 10)
+                .expect("failed to search")
+                .into_iter()
+                .map(|doc| {
+                    let doc: serde_json::Value = serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json");
+
+                    doc["n"][0].as_u64().expect("missing n")
+                })
+                .collect()
+        };
+
+        let first = page(0);
+        let second = page(10);
+        let third = page(20);
+
+        assert_eq!((0..10u64).collect::<Vec<_>>(), first);
+        assert_eq!((10..20u64).collect::<Vec<_>>(), second);
+        assert_eq!((20..25u64).collect::<Vec<_>>(), third);
+    }
+
+    #[test]
+    fn search_hits_are_ordered_by_score() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "apple" })).expect("failed to index");
+        indexer.index(json!({ "msg": "apple pear pear pear" })).expect("failed to index");
+        indexer.index(json!({ "msg": "apple pear" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<Hit> = searcher
+            .search_hits("msg:apple", 10)
+            .expect("failed to search")
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .expect("failed to read hits");
+
+        assert_eq!(3, hits.len());
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let first: serde_json::Value = serde_json::from_str(&hits[0].json).expect("invalid json");
+        assert_eq!(Some("apple"), first["msg"][0].as_str());
+    }
+
+    #[test]
+    fn search_with_min_score_drops_weak_matches() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "apple" })).expect("failed to index");
+        indexer.index(json!({ "msg": "apple pear pear pear" })).expect("failed to index");
+        indexer.index(json!({ "msg": "pear pear pear pear pear pear" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let scores: Vec<Score> = searcher
+            .search_hits("msg:apple", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|hit| hit.expect("failed to read hit").score)
+            .collect();
+
+        assert_eq!(2, scores.len());
+        assert!(scores[0] > scores[1]);
+
+        // Only the doc that's all apple is strong enough
+        let strong: Vec<serde_json::Value> = searcher
+            .search_with_min_score("msg:apple", scores[0], 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, strong.len());
+        assert_eq!(Some("apple"), strong[0]["msg"][0].as_str());
+
+        let all = searcher
+            .search_with_min_score("msg:apple", 0.0, 10)
+            .expect("failed to search")
+            .into_iter()
+            .count();
+
+        assert_eq!(2, all);
+    }
+
+    #[test]
+    fn compressed_sources_are_decompressed() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(Options::new().source(true).compress_source(true));
+
+        let lines: Vec<String> = (0..500).map(|i| format!("line {} of a very verbose log message", i)).collect();
+        let record = json!({ "msg": "verbose", "lines": lines });
+
+        indexer.index(&record).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let sources: Vec<String> = searcher
+            .search_source("msg:verbose", 10)
+            .expect("failed to search")
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .expect("failed to read sources");
+
+        assert_eq!(1, sources.len());
+        assert_eq!(record, serde_json::from_str::<serde_json::Value>(&sources[0]).expect("invalid json"));
+
+        // The stored source is smaller than the original
+        let compressed = crate::schema::Doc::build_with(&record, &Options::new().source(true).compress_source(true)).expect("failed to build doc");
+        let stored = compressed.to_json_value()[SOURCE_FIELD].as_str().expect("missing source").to_owned();
+
+        assert!(base64::decode(&stored).expect("invalid base64").len() < serde_json::to_string(&record).expect("invalid json").len());
+    }
+
+    #[test]
+    fn search_hits_are_labeled_with_their_index() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "msg": "apple" });
+        let b = json!({ "msg": "apple", "id": 1 });
+
+        let a_id = crate::schema::Doc::build(&a).expect("failed to build doc").index();
+        let b_id = crate::schema::Doc::build(&b).expect("failed to build doc").index();
+
+        indexer.index(&a).expect("failed to index");
+        indexer.index(&b).expect("failed to index");
+
+        let searcher = Searcher::new(store.clone());
+        let mut hits: Vec<Hit> = searcher
+            .search_hits("msg:apple", 10)
+            .expect("failed to search")
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .expect("failed to read hits");
+
+        hits.sort_by_key(|hit| hit.json.len());
+
+        assert_eq!(2, hits.len());
+
+        assert_eq!(a_id, hits[0].index);
+        assert_eq!(b_id, hits[1].index);
+
+        assert_eq!(store.index_name(a_id), hits[0].name);
+        assert_eq!(store.index_name(b_id), hits[1].name);
+        assert_ne!(hits[0].name, hits[1].name);
+    }
+
+    #[test]
+    fn search_as_reads_back_typed_records() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Record {
+            msg: String,
+            id: u64,
+            nested: Nested,
+            missing: Option<String>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Nested {
+            ok: bool,
+            offset: i64,
+            detail: Option<String>,
+        }
+
+        let record = Record {
+            msg: "a typed record".to_owned(),
+            id: 1,
+            nested: Nested {
+                ok: true,
+                offset: -1,
+                detail: None,
+            },
+            missing: None,
+        };
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(&record).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let records: Vec<Record> = searcher.search_as("msg:typed", 10).expect("failed to search");
+
+        assert_eq!(vec![record], records);
+    }
+
+    #[test]
+    fn search_sorted_orders_by_field() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for id in &[3, 1, 4, 2] {
+            indexer.index(json!({ "msg": "a record", "id": id })).expect("failed to index");
+        }
+
+        // An index without the field sorts as the smallest value
+        indexer.index(json!({ "msg": "a record without an id" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let ids = |ascending| -> Vec<Option<u64>> {
+            searcher
+                .search_sorted("msg:record", 10, "id", ascending)
+                .expect("failed to search")
+                .into_iter()
+                .map(|doc| {
+                    let doc: serde_json::Value = serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json");
+
+                    doc["id"][0].as_u64()
+                })
+                .collect()
+        };
+
+        assert_eq!(vec![Some(4), Some(3), Some(2), Some(1), None], ids(false));
+        assert_eq!(vec![None, Some(1), Some(2), Some(3), Some(4)], ids(true));
+
+        let top: Vec<_> = searcher
+            .search_sorted("msg:record", 2, "id", false)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str::<serde_json::Value>(&doc.expect("failed to read doc")).expect("invalid json")["id"][0].as_u64())
+            .collect();
+
+        assert_eq!(vec![Some(4), Some(3)], top);
+    }
+
+    #[test]
+    fn invalid_queries_are_query_parse_errors() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "a": "text" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        match searcher.search("missing:text", 10).map(|docs| docs.into_iter().count()) {
+            Err(Error::QueryParse(_)) => (),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(docs) => panic!("unexpected {} docs", docs),
+        }
+    }
+
+    #[test]
+    fn array_elements_are_returned_as_values_of_one_field() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "d": [13, 42, 7] })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .all(10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(json!([13, 42, 7]), hits[0]["d"]);
+    }
+
+    #[test]
+    fn float_arrays_keep_every_element() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "v": [1.5, 2.5] })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .all(10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(vec![json!({ "v": [1.5, 2.5] })], hits);
+
+        let docs: Vec<serde_json::Value> = searcher.search_as("*", 10).expect("failed to search");
+        assert_eq!(vec![json!({ "v": [1.5, 2.5] })], docs);
+
+        // Every element is indexed, not just the first
+        let matching = searcher
+            .search_filtered("*", &QuerySpec::new().float_range("v", 2.0, 3.0), 10)
+            .expect("failed to search")
+            .into_iter()
+            .count();
+        assert_eq!(1, matching);
+
+        let counts = searcher.aggregate("*", "v", 0).expect("failed to aggregate");
+        assert_eq!(vec![("1.5".to_owned(), 1), ("2.5".to_owned(), 1)], counts);
+    }
+
+    #[test]
+    fn multivalued_fields_match_any_value() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "tagged", "tags": ["a", "b", "c"] })).expect("failed to index");
+        indexer.index(json!({ "msg": "tagged", "tags": ["d"] })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<serde_json::Value> = searcher
+            .search("tags:b", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, hits.len());
+        assert_eq!(json!(["a", "b", "c"]), hits[0]["tags"]);
+    }
+
+    #[test]
+    fn search_source_returns_the_original_document() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(Options::new().source(true));
+
+        let record = json!({
+            "msg": "a nested record",
+            "ctx": {
+                "tags": ["a", "b"],
+                "missing": null,
+                "inner": { "n": 1 }
+            }
+        });
+
+        indexer.index(&record).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let sources: Vec<String> = searcher
+            .search_source("msg:nested", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(vec![serde_json::to_string(&record).expect("failed to serialize")], sources);
+    }
+
+    #[test]
+    fn count_returns_the_number_of_matches() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for _ in 0..5 {
+            indexer.index(json!({ "level": "ERROR", "msg": "failed" })).expect("failed to index");
+        }
+
+        for _ in 0..3 {
+            indexer.index(json!({ "level": "INFO", "msg": "ok" })).expect("failed to index");
+        }
+
+        // A different shape in another index
+        indexer.index(json!({ "level": "INFO" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        assert_eq!(5, searcher.count("level:ERROR").expect("failed to count"));
+    }
+
+    #[test]
+    fn query_builder_combines_terms_and_ranges() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "name": "log", "id": 5 })).expect("failed to index");
+        indexer.index(json!({ "name": "log", "id": 20 })).expect("failed to index");
+        indexer.index(json!({ "name": "other", "id": 6 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let query = QueryBuilder::term("name", "log").and(QueryBuilder::range("id", 1, 10));
+
+        let docs: Vec<serde_json::Value> = searcher
+            .search_query(&query, 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(Some(5), docs[0]["id"][0].as_u64());
+    }
+
+    #[test]
+    fn searches_see_new_commits() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+        let searcher = Searcher::new(store);
+
+        indexer.index(json!({ "msg": "first" })).expect("failed to index");
+        assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+
+        // Searching again without a commit reuses the loaded searchers
+        assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "msg": "second" })).expect("failed to index");
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn search_projected_returns_only_named_fields() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "timestamp": 1, "level": "INFO", "msg": "a record", "module": "a", "line": 42 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .search_projected("msg:record", 10, &["timestamp", "msg", "missing"])
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(vec![json!({ "timestamp": [1], "msg": ["a record"] })], docs);
+    }
+
+    #[test]
+    fn aggregate_counts_values_across_indexes() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for _ in 0..3 {
+            indexer.index(json!({ "level": "INFO", "msg": "a record" })).expect("failed to index");
+        }
+
+        for _ in 0..2 {
+            indexer.index(json!({ "level": "WARN", "msg": "a record", "extra": 1 })).expect("failed to index");
+        }
+
+        indexer.index(json!({ "level": "ERROR", "msg": "a record" })).expect("failed to index");
+        indexer.index(json!({ "level": "ERROR", "msg": "something else" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let counts = searcher.aggregate("msg:record", "level", 10).expect("failed to aggregate");
+
+        assert_eq!(vec![
+            ("INFO".to_owned(), 3),
+            ("WARN".to_owned(), 2),
+            ("ERROR".to_owned(), 1),
+        ], counts);
+
+        let top = searcher.aggregate("msg:record", "level", 1).expect("failed to aggregate");
+        assert_eq!(vec![("INFO".to_owned(), 3)], top);
+    }
+
+    #[test]
+    fn zero_limit_returns_every_hit() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for n in 0..20 {
+            indexer.index(json!({ "msg": "a record", "n": n })).expect("failed to index");
+        }
+
+        indexer.index(json!({ "msg": "a record record record" })).expect("failed to index");
+        indexer.index(json!({ "msg": "something else" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits: Vec<Hit> = searcher
+            .search_hits("msg:record", 0)
+            .expect("failed to search")
+            .into_iter()
+            .map(|hit| hit.expect("failed to read hit"))
+            .collect();
+
+        assert_eq!(21, hits.len());
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert!(hits[0].json.contains("record record record"));
+
+        assert_eq!(22, searcher.all(0).expect("failed to search").into_iter().count());
+        assert_eq!(21, searcher.search_sorted("msg:record", 0, "n", true).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn has_field_matches_docs_with_a_value() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).widening();
+
+        indexer.index(json!({ "msg": "without" })).expect("failed to index");
+        indexer.index(json!({ "msg": "null", "path": null })).expect("failed to index");
+        indexer.index(json!({ "msg": "with", "path": "/var/log" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .has_field("path", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert_eq!(Some("with"), docs[0]["msg"][0].as_str());
+    }
+
+    #[test]
+    fn prefix_queries_match_paths_and_keywords() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(Options::new().keyword_field("host"));
+
+        #[derive(Serialize)]
+        struct Request {
+            path: Path<&'static str>,
+            host: &'static str,
+        }
+
+        indexer.index(Request { path: Path("/monkey/banana"), host: "web-01.example" }).expect("failed to index");
+        indexer.index(Request { path: Path("/giraffe"), host: "db-01.example" }).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let count = |query: QueryBuilder| searcher.search_query(&query, 0).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, count(QueryBuilder::prefix("path", "/monk")));
+        assert_eq!(0, count(QueryBuilder::prefix("path", "/banana")));
+        assert_eq!(1, count(QueryBuilder::prefix("host", "web-0")));
+        assert_eq!(2, count(QueryBuilder::prefix("host", "")));
+    }
+
+    #[test]
+    fn raw_field_hints_match_exactly() {
+        let record = json!({ "path": "/var/log/app.log", "status": "404" });
+
+        let hinted = Options::new()
+            .field_hint("path", FieldHint::Raw)
+            .field_hint("status", FieldHint::Numeric);
+
+        assert_ne!(
+            crate::schema::Doc::build(&record).expect("failed to build doc").index(),
+            crate::schema::Doc::build_with(&record, &hinted).expect("failed to build doc").index()
+        );
+
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(hinted);
+
+        indexer.index(&record).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let count = |query: QueryBuilder| searcher.search_query(&query, 0).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, count(QueryBuilder::term("path", "/var/log/app.log")));
+        assert_eq!(0, count(QueryBuilder::term("path", "log")));
+        assert_eq!(1, count(QueryBuilder::range("status", 400, 500)));
+    }
+
+    #[test]
+    fn search_in_only_searches_one_index() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "msg": "a record" });
+        let b = json!({ "msg": "a record", "id": 1 });
+
+        for _ in 0..3 {
+            indexer.index(&a).expect("failed to index");
+            indexer.index(&b).expect("failed to index");
+        }
+
+        let a_id = crate::schema::Doc::build(&a).expect("failed to build doc").index();
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher
+            .search_in(a_id, "msg:record", 0)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+
+        assert_eq!(3, docs.len());
+        assert!(docs.iter().all(|doc| doc.get("id").is_none()));
+
+        match searcher.search_in(42, "msg:record", 0) {
+            Err(Error::MissingIndex(42)) => (),
+            _ => panic!("expected a missing index"),
+        }
+    }
+
+    #[test]
+    fn search_highlighted_marks_matching_terms() {
+        let store = Store::with_config(Config::default().default_field("msg"));
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "A structured log" })).expect("failed to index");
+        indexer.index(json!({ "id": 1 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let hits = searcher.search_highlighted("log", 10).expect("failed to search");
+
+        assert_eq!(1, hits.len());
+
+        let snippet = hits[0].snippet.as_ref().expect("missing snippet");
+        assert!(snippet.contains("<b>log</b>"), "{}", snippet);
+    }
+
+    #[test]
+    fn search_highlighted_reads_docs_from_the_cache() {
+        let store = Store::with_config(Config::default().default_field("msg"));
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "A structured log" })).expect("failed to index");
+
+        let searcher = Searcher::with_cache(store.clone(), 10);
+
+        searcher.search_highlighted("log", 10).expect("failed to search");
+        let hits = searcher.search_highlighted("log", 10).expect("failed to search");
+
+        assert_eq!(1, searcher.cache_hits());
+        assert_eq!(2, store.stats().searches());
+
+        let snippet = hits[0].snippet.as_ref().expect("missing snippet");
+        assert!(snippet.contains("<b>log</b>"), "{}", snippet);
+    }
+
+    #[test]
+    fn search_fuzzy_matches_nearby_terms() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "name": "log" })).expect("failed to index");
+        indexer.index(json!({ "name": "tree" })).expect("failed to index");
+        indexer.index(json!({ "id": 1 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let docs: Vec<String> = searcher
+            .search_fuzzy("name", "lug", 1, 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| doc.expect("failed to read doc"))
+            .collect();
+
+        assert_eq!(1, docs.len());
+        assert!(docs[0].contains("\"log\""));
+
+        let docs = searcher.search_fuzzy("name", "lug", 0, 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(0, docs);
+    }
+
+    #[test]
+    fn bools_are_indexed_as_numbers() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "active": true })).expect("failed to index");
+        indexer.index(json!({ "active": false })).expect("failed to index");
+
+        let searcher = Searcher::new(store.clone());
+
+        let docs: Vec<serde_json::Value> = searcher.search_as("active:1", 10).expect("failed to search");
+        assert_eq!(vec![json!({ "active": true })], docs);
+
+        let docs = searcher.search_query(&QueryBuilder::term("active", "true"), 10).expect("failed to search").into_iter().count();
+        assert_eq!(1, docs);
+
+        let docs = searcher.search_query(&QueryBuilder::term("active", "false"), 10).expect("failed to search").into_iter().count();
+        assert_eq!(1, docs);
+
+        let counts = searcher.aggregate("active:0 OR active:1", "active", 0).expect("failed to aggregate");
+        assert_eq!(vec![("false".to_owned(), 1), ("true".to_owned(), 1)], counts);
+
+        // Plain searches render booleans too, not their indexed numbers
+        let docs: Vec<serde_json::Value> = searcher
+            .search("active:1", 10)
+            .expect("failed to search")
+            .into_iter()
+            .map(|doc| serde_json::from_str(&doc.expect("failed to read doc")).expect("invalid json"))
+            .collect();
+        assert_eq!(vec![json!({ "active": [true] })], docs);
+    }
+
+    #[test]
+    fn bools_in_older_text_fields_are_rendered_as_bools() {
+        let mut schema = tantivy::schema::SchemaBuilder::new();
+        let active = schema.add_text_field("active", tantivy::schema::STRING | tantivy::schema::STORED);
+
+        let index = Index::create_in_ram(schema.build());
+
+        {
+            let mut writer = index.writer_with_num_threads(1, 10_000_000).expect("failed to create writer");
+
+            let mut doc = tantivy::Document::new();
+            doc.add_text(active, "true");
+
+            writer.add_document(doc);
+            writer.commit().expect("failed to commit");
+        }
+
+        index.load_searchers().expect("failed to load searchers");
+        let searcher = index.searcher();
+
+        let rendered: serde_json::Value = serde_json::from_str(&render(&index, &searcher, DocAddress(0, 0)).expect("failed to render")).expect("invalid json");
+
+        assert_eq!(json!({ "active": [true] }), rendered);
+    }
+
+    #[test]
+    fn case_insensitive_fields_ignore_case() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone()).with_options(Options::new().case_insensitive_field("status"));
+
+        indexer.index(json!({ "status": "Info" })).expect("failed to index");
+        indexer.index(json!({ "status": "Warn" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+
+        let docs: Vec<serde_json::Value> = searcher.search_as("status:info", 10).expect("failed to search");
+        assert_eq!(vec![json!({ "status": "Info" })], docs);
+
+        let count = |query: QueryBuilder| searcher.search_query(&query, 10).expect("failed to search").into_iter().count();
+
+        assert_eq!(1, count(QueryBuilder::term("status", "INFO")));
+        assert_eq!(1, count(QueryBuilder::prefix("status", "WA")));
+
+        // Values are still kept whole
+        assert_eq!(0, count(QueryBuilder::term("status", "inf")));
+    }
+
+    #[test]
+    fn cancelled_searches_stop_collecting() {
+        let cancel = AtomicBool::new(false);
+        let interrupt = Interrupt {
+            cancel: Some(&cancel),
+            deadline: None,
+        };
+
+        let mut collector = MultiIndexCollector::with_limit(0);
+
+        {
+            let mut collector = CurrentIndexCollector::begin(1, &mut collector).interruptible(&interrupt);
+
+            for doc in 0..10 {
+                collector.collect(doc, 1.0);
+            }
+
+            cancel.store(true, AtomicOrdering::Relaxed);
+
+            for doc in 10..1000 {
+                collector.collect(doc, 1.0);
+            }
+
+            assert!(collector.interrupted);
+        }
+
+        assert_eq!(10, collector.top_docs().into_iter().count());
+    }
+
+    #[test]
+    fn interrupted_searches_return_an_error() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for n in 0..10 {
+            indexer.index(json!({ "msg": "a record", "n": n })).expect("failed to index");
+        }
+
+        let searcher = Searcher::new(store);
+
+        let cancel = AtomicBool::new(true);
+        match searcher.search_cancellable("msg:record", 10, &cancel) {
+            Err(Error::Cancelled) => (),
+            _ => panic!("expected the search to be cancelled"),
+        }
+
+        match searcher.search_with_timeout("msg:record", 10, Duration::from_secs(0)) {
+            Err(Error::Cancelled) => (),
+            _ => panic!("expected the search to time out"),
+        }
+
+        let docs = searcher.search_with_timeout("msg:record", 10, Duration::from_secs(60)).expect("failed to search").into_iter().count();
+        assert_eq!(10, docs);
+    }
+
+    #[test]
+    fn floats_are_returned_as_floats() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for (id, latency) in [3.14, 1.0, -2.5].iter().enumerate() {
+            indexer.index(json!({ "id": id, "latency": latency })).expect("failed to index");
+        }
+
+        let searcher = Searcher::new(store);
+        let mut docs: Vec<serde_json::Value> = searcher.search_as("id:0 OR id:1 OR id:2", 10).expect("failed to search");
+        docs.sort_by_key(|doc| doc["id"].as_u64());
+
+        let latencies: Vec<f64> = docs.iter().map(|doc| doc["latency"].as_f64().expect("missing float")).collect();
+
+        assert_eq!(vec![3.14, 1.0, -2.5], latencies);
+        assert!(docs.iter().all(|doc| doc["latency"].is_f64()));
+    }
+
+    #[test]
+    fn bare_terms_search_text_fields() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "A structured log", "id": 1 })).expect("failed to index");
+        indexer.index(json!({ "msg": "Another record", "id": 2 })).expect("failed to index");
+        indexer.index(json!({ "id": 3 })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        let docs: Vec<serde_json::Value> = searcher.search_as("log", 10).expect("failed to search");
+
+        assert_eq!(vec![json!({ "msg": "A structured log", "id": 1 })], docs);
     }
 }
@@ -0,0 +1,173 @@
+/*!
+Counters for indexing and searching.
+*/
+
+use std::{
+    fmt::Write,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+    time::Duration,
+};
+
+/**
+Counters for the documents indexed into a store and the searches run over it.
+
+Counters are plain atomics, so updating them doesn't take any locks. They're
+shared by all clones of a store and only ever go up.
+*/
+#[derive(Debug, Default)]
+pub struct Stats {
+    docs_indexed: AtomicUsize,
+    commits: AtomicUsize,
+    index_errors: AtomicUsize,
+    searches: AtomicUsize,
+    search_micros: AtomicUsize,
+}
+
+impl Stats {
+    /**
+    The number of documents written to an index.
+    */
+    pub fn docs_indexed(&self) -> usize {
+        self.docs_indexed.load(Ordering::Relaxed)
+    }
+
+    /**
+    The number of times an index was committed.
+    */
+    pub fn commits(&self) -> usize {
+        self.commits.load(Ordering::Relaxed)
+    }
+
+    /**
+    The number of documents that couldn't be indexed.
+    */
+    pub fn index_errors(&self) -> usize {
+        self.index_errors.load(Ordering::Relaxed)
+    }
+
+    /**
+    The number of searches that were run.
+    */
+    pub fn searches(&self) -> usize {
+        self.searches.load(Ordering::Relaxed)
+    }
+
+    /**
+    The total time spent running searches.
+
+    This doesn't include the time spent reading the documents they return.
+    */
+    pub fn search_time(&self) -> Duration {
+        Duration::from_micros(self.search_micros.load(Ordering::Relaxed) as u64)
+    }
+
+    pub fn record_indexed(&self) {
+        self.docs_indexed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_commit(&self) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_index_error(&self) {
+        self.index_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_search(&self, elapsed: Duration) {
+        let micros = elapsed.as_secs() as usize * 1_000_000 + elapsed.subsec_micros() as usize;
+
+        self.searches.fetch_add(1, Ordering::Relaxed);
+        self.search_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /**
+    Render the counters in the Prometheus text format.
+
+    Search latency is rendered as the sum and count of a summary, in seconds.
+    */
+    pub fn to_prometheus(&self) -> String {
+        let mut metrics = String::new();
+
+        let counters = [
+            ("tantivy_log_docs_indexed_total", "Documents written to an index.", self.docs_indexed()),
+            ("tantivy_log_commits_total", "Commits to an index.", self.commits()),
+            ("tantivy_log_index_errors_total", "Documents that couldn't be indexed.", self.index_errors()),
+        ];
+
+        for (name, help, value) in &counters {
+            let _ = writeln!(metrics, "# HELP {} {}", name, help);
+            let _ = writeln!(metrics, "# TYPE {} counter", name);
+            let _ = writeln!(metrics, "{} {}", name, value);
+        }
+
+        let search_time = self.search_time();
+
+        let _ = writeln!(metrics, "# HELP tantivy_log_search_seconds Time spent running searches.");
+        let _ = writeln!(metrics, "# TYPE tantivy_log_search_seconds summary");
+        let _ = writeln!(metrics, "tantivy_log_search_seconds_sum {}", search_time.as_secs() as f64 + search_time.subsec_micros() as f64 / 1_000_000.0);
+        let _ = writeln!(metrics, "tantivy_log_search_seconds_count {}", self.searches());
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        index::{
+            Indexer,
+            SharedIndexer,
+        },
+        searcher::Searcher,
+        store::Store,
+    };
+
+    #[test]
+    fn indexing_and_searching_are_counted() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "first" })).expect("failed to index");
+        indexer.index(json!({ "msg": "second" })).expect("failed to index");
+        assert!(indexer.index(json!({ "msg": ["conflicting", 1] })).is_err());
+
+        let searcher = Searcher::new(store.clone());
+        searcher.search("msg:first", 10).expect("failed to search");
+        searcher.search("msg:second", 10).expect("failed to search");
+
+        let stats = store.stats();
+
+        assert_eq!(2, stats.docs_indexed());
+        assert_eq!(2, stats.commits());
+        assert_eq!(1, stats.index_errors());
+        assert_eq!(2, stats.searches());
+
+        assert!(stats.to_prometheus().contains("tantivy_log_docs_indexed_total 2\n"));
+    }
+
+    #[test]
+    fn shared_indexing_and_other_searches_are_counted() {
+        let store = Store::new();
+        let indexer = SharedIndexer::new(store.clone());
+
+        indexer.index(json!({ "msg": "first", "n": 1 })).expect("failed to index");
+        indexer.index(json!({ "msg": "second", "n": 2 })).expect("failed to index");
+
+        let searcher = Searcher::new(store.clone());
+        searcher.count("msg:first").expect("failed to count");
+        searcher.aggregate("*", "msg", 0).expect("failed to aggregate");
+        searcher.search_sorted("*", 10, "n", true).expect("failed to search");
+        searcher.search_highlighted("msg:second", 10).expect("failed to search");
+
+        let stats = store.stats();
+
+        assert_eq!(2, stats.docs_indexed());
+        assert_eq!(2, stats.commits());
+        assert_eq!(4, stats.searches());
+    }
+}
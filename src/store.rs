@@ -1,53 +1,1603 @@
 use std::{
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
     sync::{
         Arc,
         Mutex,
+        RwLock,
+        mpsc,
+    },
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    thread,
+    time::{
+        Duration,
+        SystemTime,
     },
-    collections::HashMap,
 };
 
+use serde_derive::Serialize;
+
 use tantivy::{
+    DocAddress,
+    DocId,
     Index,
     IndexWriter,
+    Searcher,
+    SegmentLocalId,
+    SegmentReader,
+    TantivyError,
+    collector::TopFieldCollector,
+    fastfield::{
+        BytesFastFieldReader,
+        FastFieldReader,
+        FastValue,
+    },
+    query::AllQuery,
+    schema::{
+        Document,
+        Field,
+        FieldType,
+        FieldValue,
+        Schema,
+        Value,
+    },
 };
 
 use crate::{
+    config::{
+        Config,
+        ReloadPolicy,
+        Storage,
+    },
     index::IndexId,
-    schema::IndexableDoc,
+    schema::{
+        from_millis,
+        is_float_field,
+        Doc,
+        FloatValues,
+        IndexableDoc,
+    },
+    stats::Stats,
+    sync::{
+        lock,
+        read,
+        write,
+    },
+    tokenizer,
 };
 
-const HEAP_SIZE: usize = 50_000_000;
+/**
+The field used to order documents by time.
+*/
+pub const TIMESTAMP_FIELD: &'static str = "timestamp";
+
+/**
+A writer for an index that's shared through the store.
+
+Tantivy only allows a single writer per index, so the store keeps one
+for each index and hands out shared handles to it.
+*/
+pub type Writer = Arc<Mutex<IndexWriter>>;
 
 #[derive(Clone)]
 pub struct Store {
-    state: Arc<Mutex<HashMap<IndexId, Index>>>,
+    config: Arc<Config>,
+    read_only: bool,
+    state: Arc<RwLock<State>>,
+    stats: Arc<Stats>,
+}
+
+struct State {
+    indexes: HashMap<IndexId, Index>,
+    writers: HashMap<IndexId, Writer>,
+    aliases: HashMap<IndexId, IndexId>,
+    times: HashMap<IndexId, TimeRange>,
+    names: HashMap<IndexId, String>,
+    committed: HashMap<IndexId, u64>,
+    loaded: HashMap<IndexId, u64>,
+    generation: u64,
+}
+
+/**
+The span of time an index has received documents over.
+
+Indexes are effectively time-sharded by the order records arrive in,
+so the range can be used to skip indexes that can't contain any
+documents for a given window. The range covers both when documents
+were committed and the dates in their `timestamp` field, if they have one.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl TimeRange {
+    pub(crate) fn at(time: SystemTime) -> Self {
+        TimeRange {
+            start: time,
+            end: time,
+        }
+    }
+
+    pub(crate) fn extend(&mut self, time: SystemTime) {
+        if time < self.start {
+            self.start = time;
+        }
+
+        if time > self.end {
+            self.end = time;
+        }
+    }
+
+    pub fn overlaps(&self, start: SystemTime, end: SystemTime) -> bool {
+        self.start <= end && start <= self.end
+    }
+}
+
+/**
+A machine-readable description of the indexes in a store.
+*/
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub indexes: Vec<IndexManifest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexManifest {
+    pub id: IndexId,
+    pub fields: Vec<FieldManifest>,
+    pub docs: u64,
+    pub segments: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldManifest {
+    pub name: String,
+    pub ty: &'static str,
 }
 
 impl Store {
     pub fn new() -> Self {
+        Store::with_config(Config::default())
+    }
+
+    /**
+    Create a store with the given config.
+
+    The config isn't checked until an index writer is created, so an invalid
+    config returns errors when documents are indexed. If the config keeps
+    indexes on disk, any indexes that are already there aren't loaded.
+    Use `Store::open` to load them.
+    */
+    pub fn with_config(config: Config) -> Self {
         Store {
-            state: Arc::new(Mutex::new(HashMap::new()))
+            config: Arc::new(config),
+            read_only: false,
+            state: Arc::new(RwLock::new(State {
+                indexes: HashMap::new(),
+                writers: HashMap::new(),
+                aliases: HashMap::new(),
+                times: HashMap::new(),
+                names: HashMap::new(),
+                committed: HashMap::new(),
+                loaded: HashMap::new(),
+                generation: 0,
+            })),
+            stats: Arc::new(Stats::default()),
+        }
+    }
+
+    /**
+    Create a store that keeps indexes in the given directory.
+
+    Indexes that already exist in the directory are loaded, so data
+    logged before a restart can be searched straight away.
+    */
+    pub fn on_disk(path: impl Into<PathBuf>) -> Result<Self, crate::Error> {
+        Store::open(Config::new().storage(Storage::Dir(path.into())))
+    }
+
+    /**
+    Create a store with the given config, loading any indexes that already exist on disk.
+
+    The time range of a loaded index is rebuilt from the dates in its `timestamp`
+    field. Loaded indexes without a date `timestamp` field don't have a recorded
+    time range until they're written to again, so they're skipped by time range searches.
+    */
+    pub fn open(config: Config) -> Result<Self, crate::Error> {
+        config.validate()?;
+
+        let store = Store::with_config(config);
+
+        store.refresh()?;
+
+        Ok(store)
+    }
+
+    /**
+    Open a store in the given directory for searching only.
+
+    A read-only store never creates writers, so it can be searched while
+    another store or process is writing to the same directory. Searches
+    reload each index, so they see documents as soon as they're committed
+    to an index that was loaded. Indexes created after the store was opened
+    are loaded by calling `refresh`.
+    */
+    pub fn open_read_only(path: impl Into<PathBuf>) -> Result<Self, crate::Error> {
+        let mut store = Store::with_config(Config::new().storage(Storage::Dir(path.into())));
+        store.read_only = true;
+
+        store.refresh()?;
+
+        Ok(store)
+    }
+
+    /**
+    Get the counters for documents indexed into the store and searches run over it.
+    */
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /**
+    Whether the store was opened read-only.
+    */
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /**
+    Load any indexes on disk that the store hasn't loaded yet.
+
+    Stores that keep indexes in memory don't have anything to load.
+    */
+    pub fn refresh(&self) -> Result<(), crate::Error> {
+        if let Storage::Dir(ref root) = self.config.storage {
+            if root.exists() {
+                let mut state = write(&self.state);
+
+                for entry in fs::read_dir(root)? {
+                    let entry = entry?;
+
+                    // Each index lives in a directory named by its id and name
+                    let id = match entry.file_name().to_str().and_then(parse_index_dir) {
+                        Some(id) if entry.file_type()?.is_dir() => id,
+                        _ => continue,
+                    };
+
+                    if state.indexes.contains_key(&id) {
+                        continue;
+                    }
+
+                    let index = Index::open_in_dir(entry.path())?;
+                    tokenizer::register(&index);
+
+                    index.load_searchers()?;
+
+                    if let Some(range) = timestamp_range(&index)? {
+                        state.times.insert(id, range);
+                    }
+
+                    let generation = state.generation;
+                    state.loaded.insert(id, generation);
+
+                    state.names.insert(id, index_name(&index.schema()));
+                    state.indexes.insert(id, index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    The config the store was created with.
+    */
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /**
+    Get the cached writer for an index, if one has been created.
+    */
+    pub fn writer(&self, index: IndexId) -> Option<Writer> {
+        read(&self.state).writers.get(&index).cloned()
+    }
+
+    /**
+    Get the writer for an existing index, creating it if it hasn't been created yet.
+
+    If the index has been widened then the writer for the index it was widened
+    into is returned. If the index doesn't exist then `None` is returned.
+    */
+    pub fn existing_writer(&self, index: IndexId) -> Result<Option<Writer>, crate::Error> {
+        if self.read_only {
+            return Err(crate::Error::ReadOnly);
+        }
+
+        let index = self.resolve(index);
+
+        if let Some(writer) = read(&self.state).writers.get(&index) {
+            return Ok(Some(writer.clone()));
+        }
+
+        let mut state = write(&self.state);
+
+        // Another thread may have created the writer since we checked
+        if let Some(writer) = state.writers.get(&index) {
+            return Ok(Some(writer.clone()));
+        }
+
+        match state.indexes.get(&index).map(|existing| self.open_writer(existing)) {
+            Some(writer) => {
+                let writer = Arc::new(Mutex::new(writer?));
+                state.writers.insert(index, writer.clone());
+
+                Ok(Some(writer))
+            },
+            None => Ok(None),
         }
     }
 
-    pub fn get_writer(&self, doc: &IndexableDoc) -> Result<IndexWriter, crate::Error> {
-        let mut state = self.state.lock().expect("poisoned state");
+    /**
+    Get the writer for a document's index, creating the index if it doesn't exist yet.
 
-        if let Some(ref index) = state.get(&doc.index) {
-            let writer = index.writer(HEAP_SIZE)?;
+    There's only ever one writer per index, which is shared by everything
+    that writes to it. If the document's index has been widened into another
+    then the document is rebuilt for the wider index's schema, so it's written
+    there instead of recreating the narrower index.
+    */
+    pub fn get_writer(&self, doc: &mut IndexableDoc) -> Result<Writer, crate::Error> {
+        self.resolve_doc(doc)?;
 
+        if let Some(writer) = self.existing_writer(doc.index)? {
             return Ok(writer);
         }
 
-        let index = Index::create_in_ram(doc.schema.clone());
-        let writer = index.writer(HEAP_SIZE)?;
+        let mut state = write(&self.state);
+
+        // Another thread may have created the index since we checked
+        if let Some(writer) = state.writers.get(&doc.index) {
+            return Ok(writer.clone());
+        }
+
+        let index = self.create_index(doc)?;
+        let writer = Arc::new(Mutex::new(self.open_writer(&index)?));
+
+        state.names.insert(doc.index, index_name(&doc.schema));
+        state.indexes.insert(doc.index, index);
+        state.writers.insert(doc.index, writer.clone());
+
+        Ok(writer)
+    }
+
+    /**
+    Record that an index committed documents at the given time.
+
+    Each commit bumps the store's generation, which can be used to tell
+    when anything derived from the contents of the store is stale.
+    */
+    pub fn record_commit(&self, index: IndexId, time: SystemTime) {
+        let mut state = write(&self.state);
+
+        state.generation += 1;
+
+        let generation = state.generation;
+        state.committed.insert(index, generation);
+
+        state.times
+            .entry(index)
+            .and_modify(|range| range.extend(time))
+            .or_insert_with(|| TimeRange::at(time));
+    }
+
+    /**
+    Record the span of dates in the `timestamp` field of documents committed to an index.
+
+    This is recorded alongside `record_commit`, so documents that are committed
+    some time after they were created are still found by time range searches.
+    */
+    pub(crate) fn record_times(&self, index: IndexId, range: TimeRange) {
+        let mut state = write(&self.state);
+
+        let times = state.times.entry(index).or_insert(range);
+        times.extend(range.start);
+        times.extend(range.end);
+    }
+
+    /**
+    Record that the documents in an index changed without new ones being written.
+
+    This happens when documents are deleted or segments are merged. Searchers
+    need to be reloaded and anything cached about the index is stale, but its
+    time range doesn't change.
+    */
+    pub fn record_change(&self, index: IndexId) {
+        let mut state = write(&self.state);
+
+        state.generation += 1;
+
+        let generation = state.generation;
+        state.committed.insert(index, generation);
+    }
+
+    /**
+    Get the index that documents with the given id are written to.
+
+    This is the id itself unless the index has been widened into another.
+    */
+    pub fn resolve(&self, index: IndexId) -> IndexId {
+        let state = read(&self.state);
+
+        let mut index = index;
+        while let Some(widened) = state.aliases.get(&index) {
+            index = *widened;
+        }
+
+        index
+    }
+
+    /**
+    Rebuild a document for the index its index has been widened into, if there is one.
+    */
+    fn resolve_doc(&self, doc: &mut IndexableDoc) -> Result<(), crate::Error> {
+        let target = self.resolve(doc.index);
+
+        if target == doc.index {
+            return Ok(());
+        }
+
+        let schema = match self.index(target) {
+            Some(index) => index.schema(),
+            None => return Err(crate::Error::MissingIndex(target)),
+        };
+
+        let mut widened = Document::new();
+        for value in doc.doc.field_values() {
+            widened.add(FieldValue::new(widened_field(&doc.schema, &schema, value.field())?, value.value().clone()));
+        }
+
+        doc.index = target;
+        doc.schema = schema;
+        doc.doc = widened;
 
-        state.insert(doc.index, index);
+        Ok(())
+    }
+
+    /**
+    Find an existing index that the given document could widen.
+
+    An index can be widened if its fields are a strict subset of the document's,
+    and each of them holds the same kind of value as the document's field. Some
+    kinds of values, like strings and JSON, build the same tantivy field, so kinds
+    are compared by the id the index would have if it was built from just those
+    fields of the document.
+    */
+    pub fn find_narrower(&self, doc: &Doc) -> Option<IndexId> {
+        let fields = doc.field_names();
+
+        self.indexes()
+            .into_iter()
+            .find(|(id, index)| {
+                let schema = index.schema();
+                let narrower: HashSet<&str> = schema.fields().iter().map(|field| field.name()).collect();
+
+                narrower.len() < fields.len()
+                    && narrower.is_subset(&fields)
+                    && doc.index_of(|name| narrower.contains(name)) == *id
+            })
+            .map(|(id, _)| id)
+    }
+
+    /**
+    Replace an index with a wider one for the given document.
+
+    Documents in the narrower index are copied into a new index with the
+    document's schema, and future documents for the narrower index are
+    written to the new one instead. Stored fields are copied from the stored
+    document, and fields that are only fast fields are read back from them.
+    Documents that were written to the narrower index but not committed are
+    committed before they're copied.
+
+    The narrower index's writer is locked until the wider index replaces it, so it
+    mustn't already be locked by the caller.
+    */
+    pub fn widen(&self, from: IndexId, doc: &IndexableDoc) -> Result<Writer, crate::Error> {
+        if self.read_only {
+            return Err(crate::Error::ReadOnly);
+        }
+
+        let narrower = match self.index(from) {
+            Some(index) => index,
+            None => return Err(crate::Error::MissingIndex(from)),
+        };
+
+        // Documents written to the narrower index but not committed yet are committed
+        // so they're copied along with the rest. The writer stays locked until the
+        // wider index is swapped in, so nothing else can be written to it in between
+        let narrower_writer = self.writer(from);
+        let mut narrower_lock = narrower_writer.as_ref().map(|writer| lock(writer));
+
+        if let Some(ref mut narrower_writer) = narrower_lock {
+            narrower_writer.commit()?;
+        }
+
+        // The wider index is built and committed before the store is changed, so if
+        // anything fails the narrower index is left as it was
+        let widened = self.create_index(doc).and_then(|index| {
+            let writer = self.copy_widened(from, &narrower, &index, doc)?;
+
+            Ok((index, writer))
+        });
+
+        let (index, writer) = match widened {
+            Ok(widened) => widened,
+            Err(err) => {
+                // Don't leave a partial index behind to be loaded when the store is opened
+                let _ = self.remove_index_dir(doc.index, &doc.schema);
+
+                return Err(err);
+            },
+        };
+
+        let writer = Arc::new(Mutex::new(writer));
+
+        {
+            let mut state = write(&self.state);
+
+            state.indexes.remove(&from);
+            state.indexes.insert(doc.index, index);
+
+            state.names.remove(&from);
+            state.names.insert(doc.index, index_name(&doc.schema));
+
+            state.writers.remove(&from);
+            state.writers.insert(doc.index, writer.clone());
+
+            state.aliases.insert(from, doc.index);
+
+            if let Some(range) = state.times.remove(&from) {
+                let widened = state.times.entry(doc.index).or_insert(range);
+                widened.extend(range.start);
+                widened.extend(range.end);
+            }
+
+            state.generation += 1;
+
+            let generation = state.generation;
+            state.committed.insert(doc.index, generation);
+        }
+
+        drop(narrower_lock);
+
+        // Aliases aren't persisted, so the narrower index is removed
+        // to stop its documents being loaded twice when the store is opened
+        self.remove_index_dir(from, &narrower.schema())?;
 
         Ok(writer)
     }
 
+    /**
+    Copy the documents in a narrower index into a new wider one and commit them.
+    */
+    fn copy_widened(&self, from: IndexId, narrower: &Index, index: &Index, doc: &IndexableDoc) -> Result<IndexWriter, crate::Error> {
+        let mut writer = self.open_writer(index)?;
+
+        self.load_latest_searchers(from, narrower)?;
+        let searcher = narrower.searcher();
+        let narrower_schema = narrower.schema();
+
+        let widened_field = |field: Field| widened_field(&narrower_schema, &doc.schema, field);
+
+        for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+            let fast_fields = fast_only_fields(&narrower_schema, reader)?;
+
+            for doc_id in 0..reader.max_doc() {
+                if reader.is_deleted(doc_id) {
+                    continue;
+                }
+
+                let narrower_doc = searcher.doc(DocAddress(segment as SegmentLocalId, doc_id))?;
+                let mut widened_doc = Document::new();
+
+                for value in narrower_doc.field_values() {
+                    widened_doc.add(FieldValue::new(widened_field(value.field())?, value.value().clone()));
+                }
+
+                // Fields that aren't stored, like floats and bytes, are read back from their fast fields
+                for (field, values) in &fast_fields {
+                    let field = widened_field(*field)?;
+
+                    for value in values.get(doc_id) {
+                        widened_doc.add(FieldValue::new(field, value));
+                    }
+                }
+
+                writer.add_document(widened_doc);
+            }
+        }
+
+        writer.commit()?;
+
+        Ok(writer)
+    }
+
+    /**
+    Remove the directory of an index kept on disk.
+
+    Stores that keep indexes in memory don't have anything to remove.
+    */
+    fn remove_index_dir(&self, id: IndexId, schema: &Schema) -> Result<(), crate::Error> {
+        if let Storage::Dir(ref root) = self.config.storage {
+            let dir = index_dir(root, id, schema);
+
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    Make sure the searchers for an index can see its latest commit.
+
+    Loading searchers re-reads the index's segments, so it's only done when
+    the index has been committed since the last time they were loaded. Otherwise
+    the searchers already loaded into the index's pool are reused.
+
+    Read-only stores can't see commits made by other stores, so they always
+    reload their searchers.
+
+    If the store's reload policy is `ReloadPolicy::Manual` then searchers are
+    only loaded the first time, and after that only by calling `reload`.
+    */
+    pub fn load_searchers(&self, id: IndexId, index: &Index) -> Result<(), crate::Error> {
+        {
+            let state = read(&self.state);
+
+            let stale = match (state.loaded.get(&id), state.committed.get(&id)) {
+                (Some(_), _) if self.config.reload == ReloadPolicy::Manual => return Ok(()),
+                (Some(loaded), Some(committed)) => committed > loaded,
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+
+            if !stale && !self.read_only {
+                return Ok(());
+            }
+        }
+
+        self.load_latest_searchers(id, index)
+    }
+
+    /**
+    Load the searchers for an index so they see its latest commit, whatever the reload policy is.
+
+    This is used for work that needs every committed document, like copying
+    documents between indexes or deleting them.
+    */
+    pub fn load_latest_searchers(&self, id: IndexId, index: &Index) -> Result<(), crate::Error> {
+        let generation = read(&self.state).generation;
+
+        index.load_searchers()?;
+
+        write(&self.state).loaded.insert(id, generation);
+
+        Ok(())
+    }
+
+    /**
+    Reload the searchers for every index in the store.
+
+    Searches made after reloading see all documents committed before it,
+    whatever the store's reload policy is.
+    */
+    pub fn reload(&self) -> Result<(), crate::Error> {
+        for (id, index) in self.indexes() {
+            self.load_latest_searchers(id, &index)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    The number of commits made to the store.
+    */
+    pub fn generation(&self) -> u64 {
+        read(&self.state).generation
+    }
+
+    /**
+    Get the human-readable name of an index.
+
+    The name is a sorted list of the index's fields and their types, like
+    `level:text,line:u64,msg:text`, so it describes the shape of the
+    documents in the index.
+    */
+    pub fn index_name(&self, index: IndexId) -> Option<String> {
+        read(&self.state).names.get(&index).cloned()
+    }
+
+    pub fn index(&self, index: IndexId) -> Option<Index> {
+        read(&self.state).indexes.get(&index).cloned()
+    }
+
     pub fn indexes(&self) -> impl IntoIterator<Item = (IndexId, Index)> {
-        self.state.lock().expect("poisoned state").clone()
+        read(&self.state).indexes.clone()
+    }
+
+    /**
+    Get the indexes that received documents, or hold documents with a `timestamp`, within the given range.
+
+    Indexes without any recorded time are skipped.
+    */
+    pub fn indexes_in_range(&self, start: SystemTime, end: SystemTime) -> impl IntoIterator<Item = (IndexId, Index)> {
+        let state = read(&self.state);
+
+        state.indexes
+            .iter()
+            .filter(|(id, _)| state.times.get(*id).map(|range| range.overlaps(start, end)).unwrap_or(false))
+            .map(|(id, index)| (*id, index.clone()))
+            .collect::<Vec<_>>()
+    }
+
+    /**
+    Merge the segments of each index that has a writer into a single segment.
+    */
+    pub fn compact(&self) -> Result<(), crate::Error> {
+        let writers = read(&self.state).writers.clone();
+
+        for (id, writer) in writers {
+            let index = match self.index(id) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            self.merge_segments(id, &index, &writer)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+    Merge the segments of an index into a single segment, waiting for the merge to finish.
+
+    Unlike `compact`, this creates a writer for the index if it doesn't have one
+    yet, so indexes loaded from disk can be optimized too. Merging is expensive
+    for large indexes, so it's a maintenance operation to run after heavy ingestion
+    rather than after every commit.
+    */
+    pub fn optimize(&self, id: IndexId) -> Result<(), crate::Error> {
+        let index = self.index(id).ok_or_else(|| crate::Error::MissingIndex(id))?;
+        let writer = self.existing_writer(id)?.ok_or_else(|| crate::Error::MissingIndex(id))?;
+
+        self.merge_segments(id, &index, &writer)
+    }
+
+    /**
+    Merge the segments of every index in the store.
+    */
+    pub fn optimize_all(&self) -> Result<(), crate::Error> {
+        for (id, _) in self.indexes() {
+            self.optimize(id)?;
+        }
+
+        Ok(())
+    }
+
+    fn merge_segments(&self, id: IndexId, index: &Index, writer: &Writer) -> Result<(), crate::Error> {
+        let segments = index.searchable_segment_ids()?;
+        if segments.len() < 2 {
+            return Ok(());
+        }
+
+        let merged = lock(writer).merge(&segments)?;
+
+        // Wait for the merge outside of the writer lock
+        merged.recv().map_err(|_| crate::Error::Merge(id))?;
+
+        // Merging changes the addresses of documents
+        self.record_change(id);
+
+        Ok(())
+    }
+
+    /**
+    Compact the store in the background at the given interval.
+
+    Compaction stops when the returned handle is dropped.
+    */
+    pub fn compact_every(&self, interval: Duration) -> Compaction {
+        let store = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let _ = store.compact();
+            }
+        });
+
+        Compaction {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /**
+    Get the newest document in each index.
+
+    Documents are ordered by their `timestamp` field. Indexes that don't
+    have a numeric `timestamp` fast field return the last document in
+    the index instead, which isn't guaranteed to be the newest.
+    */
+    pub fn latest_per_index(&self) -> Result<HashMap<IndexId, String>, crate::Error> {
+        let mut latest = HashMap::new();
+
+        for (id, index) in self.indexes() {
+            self.load_searchers(id, &index)?;
+
+            if let Some(doc) = latest_doc(&index)? {
+                latest.insert(id, doc);
+            }
+        }
+
+        Ok(latest)
+    }
+
+    /**
+    The number of committed documents in each index, ordered by index id.
+
+    Documents that have been written but not committed yet aren't counted.
+    */
+    pub fn index_stats(&self) -> Result<Vec<(IndexId, u64)>, crate::Error> {
+        let mut stats = Vec::new();
+
+        for (id, index) in self.indexes() {
+            self.load_searchers(id, &index)?;
+
+            stats.push((id, index.searcher().num_docs()));
+        }
+
+        stats.sort_by_key(|(id, _)| *id);
+
+        Ok(stats)
+    }
+
+    /**
+    The number of committed documents across all indexes.
+    */
+    pub fn total_docs(&self) -> Result<u64, crate::Error> {
+        Ok(self.index_stats()?.into_iter().map(|(_, docs)| docs).sum())
+    }
+
+    /**
+    Describe each index in the store, along with its fields and sizes.
+    */
+    pub fn manifest(&self) -> Result<Manifest, crate::Error> {
+        let mut indexes = Vec::new();
+
+        for (id, index) in self.indexes() {
+            self.load_searchers(id, &index)?;
+
+            let fields = index
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| FieldManifest {
+                    name: field.name().to_owned(),
+                    ty: field_type_name(field.field_type()),
+                })
+                .collect();
+
+            indexes.push(IndexManifest {
+                id,
+                fields,
+                docs: index.searcher().num_docs(),
+                segments: index.searchable_segment_ids()?.len(),
+            });
+        }
+
+        indexes.sort_by_key(|index| index.id);
+
+        Ok(Manifest {
+            indexes,
+        })
+    }
+
+    /**
+    Create a writer for an index using the heap size in the config.
+
+    Tantivy panics if it's given a heap that's too small, so the config is
+    checked first. Tantivy picks the number of threads itself, using fewer
+    than one per CPU when the heap is too small to go around.
+    */
+    fn open_writer(&self, index: &Index) -> Result<IndexWriter, crate::Error> {
+        self.config.validate()?;
+
+        Ok(index.writer(self.config.heap_size)?)
+    }
+
+    fn create_index(&self, doc: &IndexableDoc) -> Result<Index, crate::Error> {
+        let index = match self.config.storage {
+            Storage::Ram => Index::create_in_ram(doc.schema.clone()),
+            Storage::Dir(ref root) => {
+                let dir = index_dir(root, doc.index, &doc.schema);
+                fs::create_dir_all(&dir)?;
+
+                Index::create_in_dir(&dir, doc.schema.clone())?
+            },
+        };
+
+        tokenizer::register(&index);
+
+        Ok(index)
+    }
+}
+
+/**
+A handle to background compaction.
+
+Dropping the handle stops compaction and waits for the background thread to finish.
+*/
+pub struct Compaction {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Compaction {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the background thread
+        drop(self.stop.take());
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/**
+Get the date in the `timestamp` field of a document, if it has one.
+*/
+pub(crate) fn doc_time(doc: &IndexableDoc) -> Option<SystemTime> {
+    let field = doc.schema.get_field(TIMESTAMP_FIELD)?;
+
+    match doc.doc.get_first(field) {
+        Some(&Value::I64(millis)) => Some(from_millis(millis)),
+        _ => None,
+    }
+}
+
+/**
+Get the span of dates in the `timestamp` field of an index's documents.
+
+Indexes without a date `timestamp` field, or without any documents, don't have a range.
+*/
+fn timestamp_range(index: &Index) -> Result<Option<TimeRange>, crate::Error> {
+    let schema = index.schema();
+
+    let field = match schema.get_field(TIMESTAMP_FIELD) {
+        Some(field) => match *schema.get_field_entry(field).field_type() {
+            FieldType::I64(ref options) if options.is_fast() => field,
+            _ => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let mut range: Option<TimeRange> = None;
+
+    for reader in index.searcher().segment_readers() {
+        let values = reader.fast_field_reader::<i64>(field).map_err(TantivyError::from)?;
+
+        for doc in 0..reader.max_doc() {
+            if reader.is_deleted(doc) {
+                continue;
+            }
+
+            let time = from_millis(values.get(doc));
+
+            match range {
+                Some(ref mut range) => range.extend(time),
+                None => range = Some(TimeRange::at(time)),
+            }
+        }
+    }
+
+    Ok(range)
+}
+
+fn latest_doc(index: &Index) -> Result<Option<String>, crate::Error> {
+    let searcher = index.searcher();
+    let schema = index.schema();
+
+    let latest = match schema.get_field(TIMESTAMP_FIELD).map(|field| (field, schema.get_field_entry(field))) {
+        Some((field, entry)) if !is_float_field(entry) => match *entry.field_type() {
+            FieldType::I64(ref options) if options.is_fast() => newest_doc::<i64>(&searcher, field)?,
+            FieldType::U64(ref options) if options.is_fast() => newest_doc::<u64>(&searcher, field)?,
+            _ => last_doc(&searcher),
+        },
+        _ => last_doc(&searcher),
+    };
+
+    match latest {
+        Some(address) => {
+            let doc = searcher.doc(address)?;
+
+            Ok(Some(schema.to_json(&doc)))
+        },
+        None => Ok(None),
+    }
+}
+
+/**
+Find the document with the largest value in a fast field.
+
+Timestamps are compared in the field's own type, so they're never cast.
+*/
+fn newest_doc<T>(searcher: &Searcher, field: Field) -> Result<Option<DocAddress>, crate::Error>
+where
+    T: FastValue + PartialOrd,
+{
+    let mut top = TopFieldCollector::<T>::with_limit(field, 1);
+
+    searcher.search(&AllQuery, &mut top)?;
+
+    Ok(top.docs().into_iter().next())
+}
+
+/**
+Find the last document that hasn't been deleted.
+*/
+fn last_doc(searcher: &Searcher) -> Option<DocAddress> {
+    searcher
+        .segment_readers()
+        .iter()
+        .enumerate()
+        .rev()
+        .filter_map(|(segment, reader)| {
+            (0..reader.max_doc())
+                .rev()
+                .find(|doc| !reader.is_deleted(*doc))
+                .map(|doc| DocAddress(segment as SegmentLocalId, doc))
+        })
+        .next()
+}
+
+/**
+Find the field in a wider schema with the same name as a field in a narrower one.
+*/
+fn widened_field(narrower: &Schema, wider: &Schema, field: Field) -> Result<Field, crate::Error> {
+    let name = narrower.get_field_name(field);
+
+    wider.get_field(name).ok_or_else(|| crate::Error::InvalidDoc(format!("the field `{}` is missing from the wider index", name)))
+}
+
+/**
+Build the name of an index from its schema.
+*/
+fn index_name(schema: &Schema) -> String {
+    let mut fields: Vec<String> = schema
+        .fields()
+        .iter()
+        .map(|field| format!("{}:{}", field.name(), field_type_name(field.field_type())))
+        .collect();
+
+    fields.sort();
+    fields.join(",")
+}
+
+/**
+The longest index name that's included in its directory.
+*/
+const MAX_DIR_NAME_LEN: usize = 128;
+
+/**
+Get the directory an index is kept in.
+
+Directories are named by the index's id followed by its name. The name is
+only there to make the directory recognizable, so any characters that might
+not be valid in a path are replaced and long names are truncated.
+*/
+fn index_dir(root: &Path, id: IndexId, schema: &Schema) -> PathBuf {
+    let name: String = index_name(schema)
+        .chars()
+        .take(MAX_DIR_NAME_LEN)
+        .map(|c| match c {
+            ':' => '-',
+            c if c.is_ascii_alphanumeric() || c == '_' => c,
+            _ => '_',
+        })
+        .collect();
+
+    root.join(format!("{}-{}", id, name))
+}
+
+/**
+Parse the id of an index from the name of its directory.
+
+Directories named by their id alone are also accepted.
+*/
+fn parse_index_dir(dir: &str) -> Option<IndexId> {
+    dir.split('-').next().and_then(|id| id.parse().ok())
+}
+
+/**
+A reader for the values of a field that's only kept as a fast field.
+*/
+enum FastValues {
+    Floats(FloatValues),
+    I64(FastFieldReader<i64>),
+    Bytes(BytesFastFieldReader),
+}
+
+impl FastValues {
+    fn get(&self, doc: DocId) -> Vec<Value> {
+        match *self {
+            FastValues::Floats(ref values) => values.get(doc).into_iter().map(Value::U64).collect(),
+            FastValues::I64(ref values) => vec![Value::I64(values.get(doc))],
+            FastValues::Bytes(ref values) => vec![Value::Bytes(values.get_val(doc).to_owned())],
+        }
+    }
+}
+
+/**
+Get readers for the fields of a segment that are fast fields but aren't stored.
+
+The values of these fields can't be read from stored documents.
+*/
+fn fast_only_fields(schema: &Schema, reader: &SegmentReader) -> Result<Vec<(Field, FastValues)>, crate::Error> {
+    let mut fields = Vec::new();
+
+    for (i, entry) in schema.fields().iter().enumerate() {
+        if entry.is_stored() {
+            continue;
+        }
+
+        let field = Field(i as u32);
+
+        let values = match *entry.field_type() {
+            FieldType::U64(_) if is_float_field(entry) => FastValues::Floats(FloatValues::open(reader, field)?),
+            FieldType::I64(ref options) if options.is_fast() => FastValues::I64(reader.fast_field_reader(field).map_err(TantivyError::from)?),
+            FieldType::Bytes => FastValues::Bytes(reader.bytes_fast_field_reader(field).map_err(TantivyError::from)?),
+            _ => continue,
+        };
+
+        fields.push((field, values));
+    }
+
+    Ok(fields)
+}
+
+fn field_type_name(ty: &FieldType) -> &'static str {
+    match *ty {
+        FieldType::Str(_) => "text",
+        FieldType::U64(_) => "u64",
+        FieldType::I64(_) => "i64",
+        FieldType::HierarchicalFacet => "facet",
+        FieldType::Bytes => "bytes",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use serde_json::json;
+
+    use crate::{
+        config::{
+            CommitPolicy,
+            ReloadPolicy,
+        },
+        index::Indexer,
+        schema::{
+            Date,
+            Doc,
+        },
+        searcher::Searcher,
+        temp::TempDir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn manifest_lists_indexes() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "a": "text" });
+        let b = json!({ "b": "text", "c": 1 });
+
+        let a_id = Doc::build(&a).expect("failed to build doc").index();
+        let b_id = Doc::build(&b).expect("failed to build doc").index();
+
+        indexer.index(&a).expect("failed to index");
+        indexer.index(&a).expect("failed to index");
+        indexer.index(&b).expect("failed to index");
+
+        let manifest = serde_json::to_value(store.manifest().expect("failed to get manifest")).expect("failed to serialize manifest");
+        let indexes = manifest["indexes"].as_array().expect("missing indexes");
+
+        assert_eq!(2, indexes.len());
+
+        let index = |id| indexes.iter().find(|index| index["id"] == json!(id)).expect("missing index");
+
+        assert_eq!(json!(2), index(a_id)["docs"]);
+        assert_eq!(json!([{ "name": "a", "ty": "text" }]), index(a_id)["fields"]);
+
+        assert_eq!(json!(1), index(b_id)["docs"]);
+        assert_eq!(2, index(b_id)["fields"].as_array().expect("missing fields").len());
+    }
+
+    #[test]
+    fn compaction_merges_segments() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let doc = json!({ "a": "text" });
+        let id = Doc::build(&doc).expect("failed to build doc").index();
+
+        for _ in 0..5 {
+            indexer.index(&doc).expect("failed to index");
+        }
+
+        let segments = || store.index(id).expect("missing index").searchable_segment_ids().expect("failed to get segments").len();
+        assert!(segments() > 1);
+
+        let compaction = store.compact_every(Duration::from_millis(10));
+
+        let mut attempts = 0;
+        while segments() > 1 && attempts < 100 {
+            thread::sleep(Duration::from_millis(10));
+            attempts += 1;
+        }
+
+        drop(compaction);
+
+        assert_eq!(1, segments());
+    }
+
+    #[test]
+    fn optimizing_merges_segments_and_keeps_docs() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "a": "text" });
+        let b = json!({ "b": "text" });
+
+        let a_id = Doc::build(&a).expect("failed to build doc").index();
+        let b_id = Doc::build(&b).expect("failed to build doc").index();
+
+        for _ in 0..10 {
+            indexer.index(&a).expect("failed to index");
+            indexer.index(&b).expect("failed to index");
+        }
+
+        let segments = |id| store.index(id).expect("missing index").searchable_segment_ids().expect("failed to get segments").len();
+        assert!(segments(a_id) > 1);
+        assert!(segments(b_id) > 1);
+
+        store.optimize(a_id).expect("failed to optimize");
+
+        assert_eq!(1, segments(a_id));
+        assert!(segments(b_id) > 1);
+
+        store.optimize_all().expect("failed to optimize");
+
+        assert_eq!(1, segments(b_id));
+        assert_eq!(20, store.total_docs().expect("failed to count docs"));
+
+        assert!(store.optimize(42).is_err());
+    }
+
+    #[test]
+    fn latest_per_index_orders_by_timestamp() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let newer = json!({ "timestamp": 2, "msg": "newer" });
+        let older = json!({ "timestamp": 1, "msg": "older" });
+
+        let id = Doc::build(&newer).expect("failed to build doc").index();
+
+        indexer.index(&newer).expect("failed to index");
+        indexer.index(&older).expect("failed to index");
+
+        let latest = store.latest_per_index().expect("failed to get latest");
+
+        assert_eq!(1, latest.len());
+        assert!(latest[&id].contains("newer"));
+    }
+
+    #[test]
+    fn latest_per_index_compares_large_unsigned_timestamps() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let newer = json!({ "timestamp": u64::max_value(), "msg": "newer" });
+        let older = json!({ "timestamp": 1u64, "msg": "older" });
+
+        let id = Doc::build(&newer).expect("failed to build doc").index();
+
+        indexer.index(&older).expect("failed to index");
+        indexer.index(&newer).expect("failed to index");
+
+        let latest = store.latest_per_index().expect("failed to get latest");
+
+        assert!(latest[&id].contains("newer"));
+    }
+
+    #[test]
+    fn on_disk_stores_are_reopened_with_their_indexes() {
+        let dir = TempDir::new();
+
+        {
+            let store = Store::on_disk(dir.path()).expect("failed to open store");
+            let mut indexer = Indexer::new(store.clone());
+
+            indexer.index(json!({ "a": "first" })).expect("failed to index");
+            indexer.index(json!({ "a": "second", "b": 1 })).expect("failed to index");
+        }
+
+        let store = Store::on_disk(dir.path()).expect("failed to open store");
+
+        assert_eq!(2, store.indexes().into_iter().count());
+
+        let searcher = Searcher::new(store.clone());
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+
+        // Indexes that were loaded can still be written to
+        let mut indexer = Indexer::new(store.clone());
+        indexer.index(json!({ "a": "third" })).expect("failed to index");
+
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn failed_widening_leaves_the_narrower_index_in_place() {
+        let dir = TempDir::new();
+
+        {
+            let store = Store::on_disk(dir.path()).expect("failed to open store");
+            let mut indexer = Indexer::new(store.clone());
+
+            let a = json!({ "a": "first" });
+            let id = Doc::build(&a).expect("failed to build doc").index();
+
+            indexer.index(&a).expect("failed to index");
+
+            // The document is missing the narrower index's field, so its documents can't be copied
+            let b = Doc::build(json!({ "b": "second", "c": "third" })).expect("failed to build doc").indexable().expect("failed to build doc");
+            assert!(store.widen(id, &b).is_err());
+
+            assert_eq!(Some(id), store.indexes().into_iter().map(|(id, _)| id).next());
+            assert_eq!(id, store.resolve(id));
+            assert_eq!(1, fs::read_dir(dir.path()).expect("failed to read dir").count());
+
+            indexer.index(json!({ "a": "second" })).expect("failed to index");
+        }
+
+        let store = Store::on_disk(dir.path()).expect("failed to open store");
+
+        let searcher = Searcher::new(store.clone());
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn reopened_stores_rebuild_time_ranges_from_timestamps() {
+        #[derive(Serialize)]
+        struct Record {
+            timestamp: Date,
+            msg: &'static str,
+        }
+
+        let dir = TempDir::new();
+
+        // 2020-01-01T00:00:00Z
+        let midnight = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        let a_minute = Duration::from_secs(60);
+
+        {
+            let store = Store::on_disk(dir.path()).expect("failed to open store");
+            let mut indexer = Indexer::new(store.clone());
+
+            indexer.index(Record { timestamp: Date(midnight), msg: "first" }).expect("failed to index");
+            indexer.index(Record { timestamp: Date(midnight + a_minute), msg: "second" }).expect("failed to index");
+        }
+
+        let store = Store::on_disk(dir.path()).expect("failed to open store");
+
+        assert_eq!(1, store.indexes_in_range(midnight, midnight).into_iter().count());
+        assert_eq!(1, store.indexes_in_range(midnight + a_minute, SystemTime::now()).into_iter().count());
+        assert_eq!(0, store.indexes_in_range(midnight - a_minute * 2, midnight - a_minute).into_iter().count());
+    }
+
+    #[test]
+    fn read_only_stores_search_while_another_store_writes() {
+        let dir = TempDir::new();
+
+        let store = Store::on_disk(dir.path()).expect("failed to open store");
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "a": "first" })).expect("failed to index");
+
+        let read_only = Store::open_read_only(dir.path()).expect("failed to open store");
+        let searcher = Searcher::new(read_only.clone());
+
+        assert_eq!(1, searcher.all(10).expect("failed to search").into_iter().count());
+
+        // Commits to a loaded index are seen straight away
+        indexer.index(json!({ "a": "second" })).expect("failed to index");
+        assert_eq!(2, searcher.all(10).expect("failed to search").into_iter().count());
+
+        // New indexes are seen after refreshing
+        indexer.index(json!({ "b": "third" })).expect("failed to index");
+        read_only.refresh().expect("failed to refresh");
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+
+        let mut read_only_indexer = Indexer::new(read_only.clone());
+        match read_only_indexer.index(json!({ "a": "fourth" })) {
+            Err(crate::Error::ReadOnly) => (),
+            r => panic!("expected a read-only error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn poisoned_stores_can_still_be_used() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "a": "before" })).expect("failed to index");
+
+        let poisoner = store.clone();
+        let _ = thread::spawn(move || {
+            let _state = poisoner.state.write().expect("poisoned state");
+
+            panic!("poisoning the store");
+        }).join();
+
+        assert!(store.state.is_poisoned());
+
+        indexer.index(json!({ "a": "after" })).expect("failed to index");
+        indexer.index(json!({ "b": "new index" })).expect("failed to index");
+
+        let searcher = Searcher::new(store);
+        assert_eq!(3, searcher.all(10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn total_docs_counts_committed_docs() {
+        let store = Store::with_config(Config::new().commit(CommitPolicy::Batch { docs: 10, interval: Duration::from_secs(60) }));
+        let mut indexer = Indexer::new(store.clone());
+
+        for n in 0..7 {
+            indexer.index(json!({ "a": "text", "n": n })).expect("failed to index");
+        }
+
+        for _ in 0..3 {
+            indexer.index(json!({ "b": "text" })).expect("failed to index");
+        }
+
+        assert_eq!(0, store.total_docs().expect("failed to count docs"));
+
+        indexer.flush().expect("failed to flush");
+
+        assert_eq!(10, store.total_docs().expect("failed to count docs"));
+
+        let mut docs: Vec<u64> = store.index_stats().expect("failed to get stats").into_iter().map(|(_, docs)| docs).collect();
+        docs.sort();
+
+        assert_eq!(vec![3, 7], docs);
+    }
+
+    #[test]
+    fn indexes_have_descriptive_names() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        let a = json!({ "msg": "text" });
+        let b = json!({ "msg": "text", "line": 42 });
+
+        indexer.index(&a).expect("failed to index");
+        indexer.index(&b).expect("failed to index");
+
+        let a_name = store.index_name(Doc::build(&a).expect("failed to build doc").index()).expect("missing name");
+        let b_name = store.index_name(Doc::build(&b).expect("failed to build doc").index()).expect("missing name");
+
+        assert_eq!("msg:text", a_name);
+        assert_eq!("line:u64,msg:text", b_name);
+    }
+
+    #[test]
+    fn heap_size_is_configurable_and_checked() {
+        let store = Store::open(Config::new().heap_size(5_000_000)).expect("failed to open store");
+        let mut indexer = Indexer::new(store.clone());
+
+        indexer.index(json!({ "a": "text" })).expect("failed to index");
+        assert_eq!(1, store.total_docs().expect("failed to count docs"));
+
+        match Store::open(Config::new().heap_size(1_000)) {
+            Err(crate::Error::InvalidConfig(_)) => (),
+            _ => panic!("expected an invalid config"),
+        }
+
+        let store = Store::with_config(Config::new().heap_size(1_000));
+        let mut indexer = Indexer::new(store);
+
+        match indexer.index(json!({ "a": "text" })) {
+            Err(crate::Error::InvalidConfig(_)) => (),
+            r => panic!("expected an invalid config, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn searches_run_concurrently() {
+        let store = Store::new();
+        let mut indexer = Indexer::new(store.clone());
+
+        for n in 0..10 {
+            indexer.index(json!({ "a": "text", "n": n })).expect("failed to index");
+            indexer.index(json!({ "b": "text" })).expect("failed to index");
+        }
+
+        let searches: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+
+                thread::spawn(move || {
+                    let searcher = Searcher::new(store);
+
+                    for _ in 0..10 {
+                        // Documents indexed while searching may or may not be seen
+                        assert!(searcher.all(0).expect("failed to search").into_iter().count() >= 20);
+                    }
+                })
+            })
+            .collect();
+
+        // Keep indexing while the searches run
+        for _ in 0..10 {
+            indexer.index(json!({ "c": "text" })).expect("failed to index");
+        }
+
+        for search in searches {
+            search.join().expect("failed to search");
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn commits_are_visible_without_reloading() {
+        let store = Store::with_config(Config::new().reload(ReloadPolicy::OnCommit));
+        let mut indexer = Indexer::new(store.clone());
+        let searcher = Searcher::new(store);
+
+        indexer.index(json!({ "msg": "first" })).expect("failed to index");
+        assert_eq!(1, searcher.search("msg:first", 10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "msg": "second" })).expect("failed to index");
+        assert_eq!(1, searcher.search("msg:second", 10).expect("failed to search").into_iter().count());
+    }
+
+    #[test]
+    fn manual_reloads_wait_for_reload() {
+        let store = Store::with_config(Config::new().reload(ReloadPolicy::Manual));
+        let mut indexer = Indexer::new(store.clone());
+        let searcher = Searcher::new(store);
+
+        indexer.index(json!({ "msg": "first" })).expect("failed to index");
+        assert_eq!(1, searcher.search("msg:first", 10).expect("failed to search").into_iter().count());
+
+        indexer.index(json!({ "msg": "second" })).expect("failed to index");
+        assert_eq!(0, searcher.search("msg:second", 10).expect("failed to search").into_iter().count());
+
+        searcher.reload().expect("failed to reload");
+        assert_eq!(1, searcher.search("msg:second", 10).expect("failed to search").into_iter().count());
+    }
+}
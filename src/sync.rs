@@ -0,0 +1,37 @@
+/*!
+Helpers for shared state.
+*/
+
+use std::sync::{
+    Mutex,
+    MutexGuard,
+    RwLock,
+    RwLockReadGuard,
+    RwLockWriteGuard,
+};
+
+/**
+Lock a mutex, recovering it if it's been poisoned.
+
+A mutex is poisoned when a thread panics while holding its lock. Everything
+behind a lock in this crate is left usable between operations, so a panic
+partway through one of them (like a record that panics while being indexed)
+shouldn't stop every operation after it from taking the lock.
+*/
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/**
+Take a read lock, recovering it if it's been poisoned.
+*/
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/**
+Take a write lock, recovering it if it's been poisoned.
+*/
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<T> {
+    lock.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
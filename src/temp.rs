@@ -0,0 +1,50 @@
+/*!
+Temporary directories for tests that keep their indexes on disk.
+*/
+
+use std::{
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/**
+A unique directory under the system's temp directory that's removed when it's dropped.
+
+Stores using the directory should be dropped before it, so it should be
+created before them.
+*/
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        let dir = env::temp_dir().join(format!("tantivy-log-{}-{}", process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed)));
+
+        // A previous run with the same process id may have left the directory behind
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create dir");
+
+        TempDir(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Failing to clean up shouldn't fail the test
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,152 @@
+use tantivy::{
+    Index,
+    tokenizer::{
+        LowerCaser,
+        RawTokenizer,
+        Token,
+        TokenStream,
+        Tokenizer,
+    },
+};
+
+pub const PATH_TOKENIZER: &'static str = "tantivy_log_path";
+
+/**
+The tokenizer for keyword fields.
+
+Keywords are kept whole, like tantivy's `raw` tokenizer, but are registered
+under their own name so they can be told apart from other raw fields.
+*/
+pub const KEYWORD_TOKENIZER: &'static str = "tantivy_log_keyword";
+
+/**
+The tokenizer for case-insensitive keyword fields.
+
+Keywords are kept whole, but lowercased, so they match regardless of case.
+*/
+pub const LOWERCASE_KEYWORD_TOKENIZER: &'static str = "tantivy_log_keyword_lowercase";
+
+/**
+Whether a tokenizer lowercases the terms it produces.
+
+Terms built outside of the tokenizer, like prefixes, need to be lowercased
+to match them.
+*/
+pub fn is_lowercased(tokenizer: &str) -> bool {
+    tokenizer == "default" || tokenizer == LOWERCASE_KEYWORD_TOKENIZER
+}
+
+/**
+Register the tokenizers used by the schema on an index.
+
+This needs to happen whenever an index is created or opened, so that
+both the index writer and query parser can find them.
+*/
+pub fn register(index: &Index) {
+    index.tokenizers().register(PATH_TOKENIZER, PathTokenizer);
+    index.tokenizers().register(KEYWORD_TOKENIZER, RawTokenizer);
+    index.tokenizers().register(LOWERCASE_KEYWORD_TOKENIZER, RawTokenizer.filter(LowerCaser));
+}
+
+/**
+A tokenizer for path-like values.
+
+The full path is kept as a single token so it can be matched exactly.
+Each parent along the way is also emitted as a token, so a query for
+`./a` will match documents with a path of `./a/b/c`.
+*/
+#[derive(Clone)]
+pub struct PathTokenizer;
+
+pub struct PathTokenStream<'a> {
+    text: &'a str,
+    ends: Vec<usize>,
+    next: usize,
+    token: Token,
+}
+
+impl<'a> Tokenizer<'a> for PathTokenizer {
+    type TokenStreamImpl = PathTokenStream<'a>;
+
+    fn token_stream(&self, text: &'a str) -> Self::TokenStreamImpl {
+        // Each token ends at a `/` or the end of the path
+        // Segments that are empty or `.` don't get their own token, so `./a`
+        // doesn't produce a `.` token that would match every relative path
+        let mut ends = Vec::new();
+        let mut start = 0;
+        for end in text.match_indices('/').map(|(i, _)| i).chain(Some(text.len())) {
+            let segment = &text[start..end];
+
+            if !segment.is_empty() && segment != "." {
+                ends.push(end);
+            }
+
+            start = end + 1;
+        }
+
+        PathTokenStream {
+            text,
+            ends,
+            next: 0,
+            token: Token {
+                offset_from: 0,
+                offset_to: 0,
+                position: 0,
+                text: String::new(),
+            },
+        }
+    }
+}
+
+impl<'a> TokenStream for PathTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        if self.next >= self.ends.len() {
+            return false;
+        }
+
+        let end = self.ends[self.next];
+
+        self.token.offset_from = 0;
+        self.token.offset_to = end;
+        self.token.position = self.next;
+        self.token.text.clear();
+        self.token.text.push_str(&self.text[..end]);
+
+        self.next += 1;
+
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut stream = PathTokenizer.token_stream(text);
+
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+
+        tokens
+    }
+
+    #[test]
+    fn paths_skip_empty_and_current_dir_segments() {
+        assert_eq!(vec!["./a", "./a/b"], tokens("./a/b"));
+        assert_eq!(vec!["/a", "/a//b"], tokens("/a//b"));
+        assert_eq!(vec!["a"], tokens("a/"));
+        assert!(tokens("").is_empty());
+        assert!(tokens(".").is_empty());
+    }
+}